@@ -1,6 +1,17 @@
 function build {
     cargo build -Z build-std=core --target avr-atmega328p.json --release
-    avr-size -C .\target\avr-atmega328p\release\nano_rl.elf
+    avr-size -C --mcu=atmega328p .\target\avr-atmega328p\release\nano_rl.elf
+}
+
+# Builds for one of the other supported microcontrollers, e.g. `build_mcu atmega2560`.
+function build_mcu($mcu) {
+    cargo build -Z build-std=core --no-default-features --features $mcu --target avr-$mcu.json --release
+    avr-size -C --mcu=$mcu .\target\avr-$mcu\release\nano_rl.elf
+}
+
+# Lists the statics in RAM, largest first, to see where the space is going.
+function ram($mcu = "atmega328p") {
+    avr-nm --size-sort --reverse-sort --print-size --radix=d .\target\avr-$mcu\release\nano_rl.elf | Select-String " [bBdD] "
 }
 
 function upload {
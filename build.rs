@@ -5,6 +5,17 @@
 // 0x40, then 8 bytes representing the 8 columns.
 // The MSB in each byte is the bottom row, LSB is the top.
 
+// It also generates the register definitions from the device's ATDF file. See build/atdf.rs.
+// And the HAL's error codes, with a table of them in error_codes.md. See build/errors.rs.
+// And the font used for text, from font.png. See build/font.rs.
+
+#[path = "build/atdf.rs"]
+mod atdf;
+#[path = "build/errors.rs"]
+mod errors;
+#[path = "build/font.rs"]
+mod font;
+
 use image::{GenericImageView, RgbaImage, SubImage};
 
 use std::{
@@ -15,17 +26,51 @@ use std::{
 
 const SSD1306_DATA: u8 = 0x40;
 const TILE_SIZE: u32 = 8;
-const NUM_TILES: u32 = 15;
-
-const TILES: [&str; 15] = [
-    "FLOOR", "WALL", "STAIRS", "ENEMY", "PLAYER", "N0", "N1", "N2", "N3", "N4", "N5", "N6", "N7",
-    "N8", "N9",
+const NUM_TILES: u32 = 5;
+
+/// The supported devices, and the name of the module their registers are generated into.
+const DEVICES: [(&str, &str); 4] = [
+    ("ATmega328P", "atmega328p"),
+    ("ATmega168", "atmega168"),
+    ("ATmega32U4", "atmega32u4"),
+    ("ATmega2560", "atmega2560"),
 ];
 
+const TILES: [&str; 5] = ["FLOOR", "WALL", "STAIRS", "ENEMY", "PLAYER"];
+
 fn main() {
-    println!("cargo::rerun-if-changes=tiles.png");
-    println!("cargo::rerun-if-changes=title_screen.png");
-    println!("cargo::rerun-if-changes=game_over.png");
+    println!("cargo:rerun-if-changed=build/atdf.rs");
+    println!("cargo:rerun-if-changed=assets/tiles.png");
+    println!("cargo:rerun-if-changed=assets/title_screen.png");
+    println!("cargo:rerun-if-changed=assets/game_over.png");
+
+    for (device, module) in DEVICES.iter() {
+        let atdf_path = Path::new("assets")
+            .join("atdf")
+            .join(device)
+            .with_extension("atdf");
+        println!("cargo:rerun-if-changed={}", atdf_path.display());
+
+        let module_path = Path::new("src")
+            .join("hal")
+            .join("device")
+            .join(module)
+            .with_extension("rs");
+        atdf::generate(&atdf_path, &module_path);
+    }
+
+    println!("cargo:rerun-if-changed=build/errors.rs");
+    errors::generate(
+        &Path::new("src").join("hal").join("error_codes.rs"),
+        Path::new("error_codes.md"),
+    );
+
+    println!("cargo:rerun-if-changed=build/font.rs");
+    println!("cargo:rerun-if-changed=assets/font.png");
+    font::generate(
+        &Path::new("assets").join("font.png"),
+        &Path::new("src").join("peripherals").join("font.rs"),
+    );
 
     let tiles = image::open("assets/tiles.png").unwrap().to_rgba();
 
@@ -49,11 +94,16 @@ fn main() {
     )
     .unwrap();
 
-    writeln!(&mut file, "use crate::hal::progmem::PGMSlice;").unwrap();
+    writeln!(&mut file, "progmem! {{").unwrap();
 
     for (name, tile) in TILES.iter().zip(tiles) {
-        writeln!(&mut file, "#[link_section = \".text\"]").unwrap();
-        write!(&mut file, "static {}_DATA: [u8; {}] = [", name, tile.len()).unwrap();
+        write!(
+            &mut file,
+            "    pub static {}: [u8; {}] = [",
+            name,
+            tile.len()
+        )
+        .unwrap();
 
         // Fortunately, rust doesn't care about trailing commas!
         for b in tile {
@@ -61,34 +111,20 @@ fn main() {
         }
 
         writeln!(&mut file, "];").unwrap();
-        write_getter(&mut file, name);
     }
 
     write_splash(&mut file, "TITLE_SCREEN", "title_screen.png");
     write_splash(&mut file, "GAME_OVER", "game_over.png");
-}
 
-fn write_getter(file: &mut BufWriter<File>, name: &str) {
-    writeln!(
-        file,
-        "
-    #[allow(non_snake_case)]
-    #[inline(always)]
-    pub fn {0}() -> PGMSlice {{
-        unsafe {{ PGMSlice::from_raw_parts(&{0}_DATA as *const u8, {0}_DATA.len()) }}
-    }}",
-        name
-    )
-    .unwrap();
+    writeln!(&mut file, "}}").unwrap();
 }
 
 fn write_splash(file: &mut BufWriter<File>, splash_name: &str, filename: &str) {
     let title_screen = process_splash(filename);
 
-    writeln!(file, "#[link_section = \".text\"]").unwrap();
     writeln!(
         file,
-        "static {}_DATA: [u8; {}] = [",
+        "    pub static {}: [u8; {}] = [",
         splash_name,
         title_screen.len()
     )
@@ -96,15 +132,14 @@ fn write_splash(file: &mut BufWriter<File>, splash_name: &str, filename: &str) {
 
     // We'll be nice, and chunk the output...
     for chunk in title_screen.chunks(32) {
-        write!(file, "    ").unwrap();
+        write!(file, "        ").unwrap();
         for b in chunk {
             write!(file, "{},", b).unwrap();
         }
         writeln!(file).unwrap();
     }
 
-    writeln!(file, "];").unwrap();
-    write_getter(file, splash_name);
+    writeln!(file, "    ];").unwrap();
 }
 
 fn process_splash(filename: &str) -> Vec<u8> {
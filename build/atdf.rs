@@ -0,0 +1,352 @@
+// Generates the register definitions from a Microchip ATDF device file.
+//
+// Each register group listed under the device's peripherals, for the modules in MODULES,
+// becomes a module holding a reg! block for each of its registers. The other modules in the
+// device file are skipped, as are groups outside of the data space, such as the fuses. Single-bit bitfields become named bits, and
+// multi-bit bitfields are split into one named bit per bit, numbered from the field's LSB,
+// which gives the same names as the datasheet (CS00, CS01, etc.).
+//
+// The write mask is made up of the bitfields that aren't read-only. Registers without any
+// bitfields, such as data registers, just use the register's mask.
+//
+// The device's interrupt list is used to generate the interrupt! macro, and the sizes of
+// its memories are given as constants.
+
+use roxmltree::{Document, Node};
+
+use std::{fmt::Write, fs, path::Path};
+
+/// The peripheral modules the HAL has drivers for. The rest of the device file is ignored.
+const MODULES: &[&str] = &["CPU", "EEPROM", "PORT", "TC8", "TWI", "USART", "WDT"];
+
+/// Multi-bit bitfields which are also given a typed field, and the type holding their values.
+///
+/// The value groups in the ATDF files don't have usable names, so the value types are written
+/// by hand alongside the drivers that use them.
+const FIELD_VALUES: &[(&str, &str, &str)] = &[
+    ("TCCR0B", "CS0", "crate::hal::clock::registers::ClockSelect"),
+    ("TWSR", "TWPS", "crate::hal::twi::registers::TWIPrescaler"),
+    ("TWSR", "TWS", "crate::hal::twi::registers::TWSRStatus"),
+    ("UCSR0C", "UPM0", "crate::hal::usart::registers::ParityMode"),
+    (
+        "UCSR0C",
+        "UMSEL0",
+        "crate::hal::usart::registers::USARTMode",
+    ),
+    ("UCSR1C", "UPM1", "crate::hal::usart::registers::ParityMode"),
+    (
+        "UCSR1C",
+        "UMSEL1",
+        "crate::hal::usart::registers::USARTMode",
+    ),
+];
+
+struct Bitfield<'a> {
+    name: &'a str,
+    caption: &'a str,
+    mask: u32,
+    access: &'a str,
+}
+
+pub fn generate(device_file: &Path, out_file: &Path) {
+    let text = fs::read_to_string(device_file).unwrap();
+    let doc = Document::parse(&text).unwrap();
+    let root = doc.root_element();
+
+    let device = child(root, "devices")
+        .and_then(|devices| child(devices, "device"))
+        .expect("No device in ATDF file");
+    let modules = child(root, "modules").expect("No modules in ATDF file");
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated by the build.rs file from {} during compilation",
+        device_file.file_name().unwrap().to_string_lossy()
+    )
+    .unwrap();
+
+    let address_spaces = child(device, "address-spaces").expect("No address spaces in ATDF file");
+    write_memory_sizes(&mut out, address_spaces);
+
+    let peripherals = child(device, "peripherals").expect("No peripherals in ATDF file");
+    let groups = peripherals
+        .descendants()
+        .filter(|n| n.has_tag_name("register-group"))
+        .filter(|n| n.attribute("address-space").unwrap_or("data") == "data");
+
+    for group_ref in groups {
+        let module_name = group_ref
+            .ancestors()
+            .find(|n| n.has_tag_name("module"))
+            .and_then(|n| n.attribute("name"))
+            .unwrap();
+        if !MODULES.contains(&module_name) {
+            continue;
+        }
+        let group_name = group_ref.attribute("name-in-module").unwrap();
+        let base = parse_int(group_ref.attribute("offset").unwrap_or("0"));
+
+        let group = modules
+            .children()
+            .filter(|n| n.has_tag_name("module") && n.attribute("name") == Some(module_name))
+            .flat_map(|n| n.children())
+            .find(|n| n.has_tag_name("register-group") && n.attribute("name") == Some(group_name))
+            .unwrap_or_else(|| panic!("Missing register group {}", group_name));
+
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "pub mod {} {{",
+            group_ref.attribute("name").unwrap().to_lowercase()
+        )
+        .unwrap();
+
+        let mut registers: Vec<_> = group
+            .children()
+            .filter(|n| n.has_tag_name("register"))
+            .collect();
+        registers.sort_by_key(|r| parse_int(r.attribute("offset").unwrap()));
+
+        for (i, register) in registers.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(out).unwrap();
+            }
+            write_register(&mut out, register, base);
+        }
+
+        writeln!(out, "}}").unwrap();
+    }
+
+    let interrupts = child(device, "interrupts").expect("No interrupts in ATDF file");
+    write_interrupts(&mut out, device.attribute("name").unwrap(), interrupts);
+
+    fs::write(out_file, out).unwrap();
+}
+
+fn write_memory_sizes(out: &mut String, address_spaces: Node) {
+    let eeprom = address_spaces
+        .children()
+        .find(|n| n.has_tag_name("address-space") && n.attribute("name") == Some("eeprom"))
+        .expect("No EEPROM in ATDF file");
+
+    writeln!(out).unwrap();
+    writeln!(out, "/// The size of the EEPROM, in bytes.").unwrap();
+    writeln!(
+        out,
+        "pub const EEPROM_SIZE: u16 = 0x{:04X};",
+        parse_int(eeprom.attribute("size").unwrap())
+    )
+    .unwrap();
+
+    let iram = address_spaces
+        .descendants()
+        .find(|n| n.has_tag_name("memory-segment") && n.attribute("name") == Some("IRAM"))
+        .expect("No internal RAM in ATDF file");
+
+    writeln!(out).unwrap();
+    writeln!(out, "/// The address of the start of the internal RAM.").unwrap();
+    writeln!(
+        out,
+        "pub const RAM_START: u16 = 0x{:04X};",
+        parse_int(iram.attribute("start").unwrap())
+    )
+    .unwrap();
+    writeln!(out, "/// The size of the internal RAM, in bytes.").unwrap();
+    writeln!(
+        out,
+        "pub const RAM_SIZE: u16 = 0x{:04X};",
+        parse_int(iram.attribute("size").unwrap())
+    )
+    .unwrap();
+}
+
+fn write_register(out: &mut String, register: Node, base: u32) {
+    let name = register.attribute("name").unwrap();
+    let addr = base + parse_int(register.attribute("offset").unwrap());
+    let (ty, digits) = match register.attribute("size") {
+        Some("1") => ("u8", 2),
+        Some("2") => ("u16", 4),
+        size => panic!("Unsupported size {:?} for register {}", size, name),
+    };
+
+    let bitfields: Vec<_> = register
+        .children()
+        .filter(|n| n.has_tag_name("bitfield"))
+        .map(|n| Bitfield {
+            name: n.attribute("name").unwrap(),
+            caption: n.attribute("caption").unwrap_or(""),
+            mask: parse_int(n.attribute("mask").unwrap()),
+            access: n.attribute("rw").unwrap_or("RW"),
+        })
+        .collect();
+
+    let write_mask = if bitfields.is_empty() {
+        register
+            .attribute("mask")
+            .map(parse_int)
+            .unwrap_or((1 << (digits * 4)) - 1)
+    } else {
+        bitfields
+            .iter()
+            .filter(|b| b.access.contains('W'))
+            .fold(0, |mask, b| mask | b.mask)
+    };
+
+    writeln!(out, "    reg! {{").unwrap();
+    write_doc(out, 2, register.attribute("caption").unwrap_or(""));
+    writeln!(out, "        {}: {} {{", name, ty).unwrap();
+    writeln!(out, "            addr: 0x{:02X},", addr).unwrap();
+    writeln!(
+        out,
+        "            write mask: 0x{:0width$X},",
+        write_mask,
+        width = digits
+    )
+    .unwrap();
+
+    if !bitfields.is_empty() {
+        // (bit, name, doc, access)
+        let mut bits = Vec::new();
+        for field in &bitfields {
+            let positions: Vec<_> = (0..digits * 4)
+                .filter(|i| field.mask & (1 << i) != 0)
+                .collect();
+
+            if let [bit] = positions[..] {
+                bits.push((
+                    bit,
+                    field.name.to_owned(),
+                    field.caption.to_owned(),
+                    field.access,
+                ));
+            } else {
+                for (i, &bit) in positions.iter().enumerate() {
+                    bits.push((
+                        bit,
+                        format!("{}{}", field.name, i),
+                        format!("{} - Bit {}", field.caption, i),
+                        field.access,
+                    ));
+                }
+            }
+        }
+        bits.sort_by_key(|b| b.0);
+
+        writeln!(out, "            bits: {{").unwrap();
+        for (bit, name, doc, access) in bits {
+            write_doc(out, 4, &doc);
+            writeln!(out, "                {} = {}, {};", name, bit, access).unwrap();
+        }
+        writeln!(out, "            }}").unwrap();
+    }
+
+    let fields: Vec<_> = bitfields
+        .iter()
+        .filter_map(|field| {
+            FIELD_VALUES
+                .iter()
+                .find(|(reg, f, _)| *reg == name && *f == field.name)
+                .map(|(_, _, value)| (field, value))
+        })
+        .collect();
+
+    if !fields.is_empty() {
+        writeln!(out, "            fields: {{").unwrap();
+        for (field, value) in fields {
+            let lo = field.mask.trailing_zeros();
+            let hi = 31 - field.mask.leading_zeros();
+            assert_eq!(
+                field.mask >> lo,
+                (1 << (hi - lo + 1)) - 1,
+                "Field {} in {} isn't contiguous",
+                field.name,
+                name
+            );
+
+            write_doc(out, 4, field.caption);
+            writeln!(
+                out,
+                "                {} = {}..={} => {}, {};",
+                field.name, lo, hi, value, field.access
+            )
+            .unwrap();
+        }
+        writeln!(out, "            }}").unwrap();
+    }
+
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+}
+
+fn write_interrupts(out: &mut String, device: &str, interrupts: Node) {
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "/// Registers a function as the handler for an interrupt vector, named as in the {}
+/// datasheet.
+///
+/// ```
+/// interrupt!(TIMER0_COMPA, tick);
+///
+/// fn tick() {{
+///     // ...
+/// }}
+/// ```
+///
+/// Using a name that isn't in the vector table fails to compile, as does registering two
+/// handlers for the same vector, as both would define the same symbol.
+#[macro_export]
+macro_rules! interrupt {{",
+        device
+    )
+    .unwrap();
+
+    let mut vectors: Vec<_> = interrupts
+        .children()
+        .filter(|n| n.has_tag_name("interrupt"))
+        .map(|n| {
+            (
+                parse_int(n.attribute("index").unwrap()),
+                n.attribute("name").unwrap(),
+            )
+        })
+        // The reset vector can't be given a handler.
+        .filter(|&(index, _)| index != 0)
+        .collect();
+    vectors.sort();
+
+    for (index, name) in vectors {
+        writeln!(out, "    ({}, $handler:path) => {{", name).unwrap();
+        writeln!(out, "        $crate::isr!(__vector_{}, $handler);", index).unwrap();
+        writeln!(out, "    }};").unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "    ($vector:ident, $handler:path) => {{").unwrap();
+    writeln!(
+        out,
+        "        compile_error!(concat!(\"Unknown interrupt vector: \", stringify!($vector)));"
+    )
+    .unwrap();
+    writeln!(out, "    }};").unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+fn write_doc(out: &mut String, indent: usize, doc: &str) {
+    if !doc.is_empty() {
+        writeln!(out, "{:width$}/// {}", "", doc, width = indent * 4).unwrap();
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn parse_int(text: &str) -> u32 {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).unwrap()
+    } else {
+        text.parse().unwrap()
+    }
+}
@@ -0,0 +1,143 @@
+// Generates the blink code for each variant of `hal::Error`, and the table of them in
+// error_codes.md.
+//
+// The codes are all listed here, so they can be checked for collisions. A code shouldn't be
+// changed or reused once it's been given out, otherwise an old note of what a blink meant would
+// be wrong.
+
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+
+struct ErrorCode {
+    code: u8,
+    /// The pattern matching the variant, relative to `Error`.
+    variant: &'static str,
+    description: &'static str,
+}
+
+/// Only the low 6 bits of the code are blinked.
+const MAX_CODE: u8 = 63;
+
+const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: 0,
+        variant: "Abort",
+        description: "`abort` was called.",
+    },
+    ErrorCode {
+        code: 1,
+        variant: "TWI(TWIError::BufferLenError)",
+        description: "The data was too long for the TWI driver's buffer.",
+    },
+    ErrorCode {
+        code: 2,
+        variant: "TWI(TWIError::SendAddressNACK)",
+        description: "No device acknowledged the TWI address. Is the display connected?",
+    },
+    ErrorCode {
+        code: 3,
+        variant: "TWI(TWIError::SendDataNACK)",
+        description: "The TWI device didn't acknowledge a data byte.",
+    },
+    ErrorCode {
+        code: 4,
+        variant: "TWI(TWIError::NotReady)",
+        description: "A TWI write was started while the driver was busy.",
+    },
+    ErrorCode {
+        code: 5,
+        variant: "TWI(TWIError::BusError)",
+        description: "The TWI bus was in an unexpected state.",
+    },
+    ErrorCode {
+        code: 7,
+        variant: "TWI(TWIError::InvalidAddress)",
+        description: "The TWI address was more than 7 bits.",
+    },
+    ErrorCode {
+        code: 10,
+        variant: "UnexpectedInterrupt",
+        description: "An interrupt fired that doesn't have a handler.",
+    },
+];
+
+pub fn generate(rust_file: &Path, table_file: &Path) {
+    let mut by_code = BTreeMap::new();
+    for error in ERROR_CODES {
+        assert!(
+            error.code <= MAX_CODE,
+            "Code {} for {} doesn't fit in 6 bits",
+            error.code,
+            error.variant
+        );
+        if let Some(other) = by_code.insert(error.code, error) {
+            panic!(
+                "Code {} is used by both {} and {}",
+                error.code, other.variant, error.variant
+            );
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated by the build.rs file from build/errors.rs during compilation"
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "use super::{{twi::TWIError, Error}};").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "impl Error {{").unwrap();
+    writeln!(out, "    /// The number blinked on the LED for this error.").unwrap();
+    writeln!(out, "    pub fn code(self) -> u8 {{").unwrap();
+    writeln!(out, "        match self {{").unwrap();
+    for error in by_code.values() {
+        writeln!(
+            out,
+            "            Error::{} => {},",
+            error.variant, error.code
+        )
+        .unwrap();
+    }
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    fs::write(rust_file, out).unwrap();
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<!-- Generated by the build.rs file from build/errors.rs during compilation -->"
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "# Error Codes").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "The code is blinked on the LED starting from the lowest bit, with a short blink (`.`) \
+         for a 0 and a long blink (`-`) for a 1. All 6 bits are always blinked, followed by a 1 \
+         second pause."
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "| Code | Blinks | Error | Meaning |").unwrap();
+    writeln!(out, "|-----:|--------|-------|---------|").unwrap();
+    for error in by_code.values() {
+        let blinks: String = (0..6)
+            .map(|bit| {
+                if error.code & (1 << bit) == 0 {
+                    '.'
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        writeln!(
+            out,
+            "| {} | `{}` | `{}` | {} |",
+            error.code, blinks, error.variant, error.description
+        )
+        .unwrap();
+    }
+    fs::write(table_file, out).unwrap();
+}
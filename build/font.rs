@@ -0,0 +1,66 @@
+// Converts the font.png file into the glyphs used by src/peripherals/text.rs.
+//
+// The image is a single row of 5x8 glyphs, for the printable ASCII characters from space to
+// tilde, in order. Each glyph is stored as 5 columns, in the same format as the tiles, without
+// the data flag in front as they're sent several at a time. The spacing between characters is
+// added when they're drawn.
+
+use image::GenericImageView;
+
+use std::{fmt::Write, fs, path::Path};
+
+const FIRST_CHAR: u8 = b' ';
+const LAST_CHAR: u8 = b'~';
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 8;
+
+pub fn generate(image_path: &Path, rust_file: &Path) {
+    let font = image::open(image_path).unwrap().to_rgba();
+
+    let num_glyphs = (LAST_CHAR - FIRST_CHAR + 1) as u32;
+    assert_eq!(font.width(), num_glyphs * GLYPH_WIDTH);
+    assert_eq!(font.height(), GLYPH_HEIGHT);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated by the build.rs file from build/font.rs during compilation"
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// The character of the first glyph.").unwrap();
+    writeln!(out, "pub const FIRST_CHAR: u8 = {};", FIRST_CHAR).unwrap();
+    writeln!(out, "/// The character of the last glyph.").unwrap();
+    writeln!(out, "pub const LAST_CHAR: u8 = {};", LAST_CHAR).unwrap();
+    writeln!(out, "/// The number of columns in each glyph.").unwrap();
+    writeln!(out, "pub const GLYPH_WIDTH: usize = {};", GLYPH_WIDTH).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "progmem! {{").unwrap();
+    writeln!(
+        out,
+        "    pub static FONT: [u8; {}] = [",
+        num_glyphs * GLYPH_WIDTH
+    )
+    .unwrap();
+
+    for (i, c) in (FIRST_CHAR..=LAST_CHAR).enumerate() {
+        let glyph = font.view(i as u32 * GLYPH_WIDTH, 0, GLYPH_WIDTH, GLYPH_HEIGHT);
+
+        write!(out, "        ").unwrap();
+        for x in 0..GLYPH_WIDTH {
+            let mut column = 0u8;
+            for y in (0..GLYPH_HEIGHT).rev() {
+                column <<= 1;
+                if glyph.get_pixel(x, y)[0] == 255 {
+                    column |= 1;
+                }
+            }
+            write!(out, "{},", column).unwrap();
+        }
+        writeln!(out, " // {:?}", c as char).unwrap();
+    }
+
+    writeln!(out, "    ];").unwrap();
+    writeln!(out, "}}").unwrap();
+    fs::write(rust_file, out).unwrap();
+}
@@ -0,0 +1,128 @@
+//! How the game is wired up on each of the supported boards.
+//!
+//! The board is picked from the selected microcontroller:
+//!
+//! * ATmega328P or ATmega168: Arduino Nano, with the buttons on D3 to D6.
+//! * ATmega32U4: SparkFun Pro Micro, with the buttons on pins 15, 16, 14 and 8.
+//! * ATmega2560: Arduino Mega, with the buttons on pins 22 to 25.
+//!
+//! The display is always on the TWI pins. The buttons must all be on the same port, as the
+//! input is read from a single snapshot of the port.
+
+use crate::hal::{
+    clock::TC0,
+    eeprom::EEPROM,
+    ports::{Floating, Input, Pin},
+    twi::{self, TWI0},
+    usart::USART0,
+    Peripherals,
+};
+
+#[cfg(any(feature = "atmega328p", feature = "atmega168"))]
+mod pins {
+    pub use crate::hal::ports::{PortD as ButtonPort, PB5 as Led, PD3, PD4, PD5, PD6};
+    pub type Left = PD3;
+    pub type Right = PD4;
+    pub type Down = PD5;
+    pub type Up = PD6;
+
+    pub const LED_ACTIVE_HIGH: bool = true;
+}
+
+#[cfg(feature = "atmega32u4")]
+mod pins {
+    // The Pro Micro doesn't have a user LED, so the RX LED is used instead.
+    pub use crate::hal::ports::{PortB as ButtonPort, PB0 as Led, PB1, PB2, PB3, PB4};
+    pub type Left = PB1;
+    pub type Right = PB2;
+    pub type Down = PB3;
+    pub type Up = PB4;
+
+    pub const LED_ACTIVE_HIGH: bool = false;
+}
+
+#[cfg(feature = "atmega2560")]
+mod pins {
+    pub use crate::hal::ports::{PortA as ButtonPort, PA0, PA1, PA2, PA3, PB7 as Led};
+    pub type Left = PA0;
+    pub type Right = PA1;
+    pub type Down = PA2;
+    pub type Up = PA3;
+
+    pub const LED_ACTIVE_HIGH: bool = true;
+}
+
+pub use pins::{ButtonPort, Down, Led, Left, Right, Up, LED_ACTIVE_HIGH};
+
+/// The peripherals and pins used by the game, taken from the `Peripherals`.
+pub struct Board {
+    pub tc0: TC0,
+    pub twi: TWI0,
+    pub usart0: USART0,
+    pub eeprom: EEPROM,
+    pub sda: Pin<twi::SDA, Input<Floating>>,
+    pub scl: Pin<twi::SCL, Input<Floating>>,
+    pub left: Pin<Left, Input<Floating>>,
+    pub right: Pin<Right, Input<Floating>>,
+    pub down: Pin<Down, Input<Floating>>,
+    pub up: Pin<Up, Input<Floating>>,
+}
+
+impl Board {
+    #[cfg(any(feature = "atmega328p", feature = "atmega168"))]
+    pub fn new(p: Peripherals) -> Board {
+        let portc = p.portc.split();
+        let portd = p.portd.split();
+
+        Board {
+            tc0: p.tc0,
+            twi: p.twi,
+            usart0: p.usart0,
+            eeprom: p.eeprom,
+            sda: portc.pc4,
+            scl: portc.pc5,
+            left: portd.pd3,
+            right: portd.pd4,
+            down: portd.pd5,
+            up: portd.pd6,
+        }
+    }
+
+    #[cfg(feature = "atmega32u4")]
+    pub fn new(p: Peripherals) -> Board {
+        let portb = p.portb.split();
+        let portd = p.portd.split();
+
+        Board {
+            tc0: p.tc0,
+            twi: p.twi,
+            usart0: p.usart0,
+            eeprom: p.eeprom,
+            sda: portd.pd1,
+            scl: portd.pd0,
+            left: portb.pb1,
+            right: portb.pb2,
+            down: portb.pb3,
+            up: portb.pb4,
+        }
+    }
+
+    #[cfg(feature = "atmega2560")]
+    pub fn new(p: Peripherals) -> Board {
+        let porta = p.porta.split();
+        let portd = p.portd.split();
+
+        Board {
+            tc0: p.tc0,
+            twi: p.twi,
+            usart0: p.usart0,
+            eeprom: p.eeprom,
+            sda: portd.pd1,
+            scl: portd.pd0,
+            left: porta.pa0,
+            right: porta.pa1,
+            down: porta.pa2,
+            up: porta.pa3,
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! A log of the last few fatal errors, kept in the EEPROM so it survives a power cycle.
+//!
+//! The log starts with a magic byte, so a blank or corrupted EEPROM can be recognised, and the
+//! index of the slot the next entry goes in. The entries follow, 4 bytes each, written in a
+//! ring so the oldest is overwritten first.
+//!
+//! Holding the left button at power-on shows the log on the display, and sends it over the
+//! USART.
+
+use crate::{
+    hal::{
+        device::{cpu::MCUSR, wdt::WDTCSR},
+        eeprom::EEPROM,
+        interrupt,
+        register::Register,
+        twi,
+        usart::USART,
+    },
+    peripherals::display::Display,
+};
+
+/// Where the log starts in the EEPROM.
+const LOG_ADDR: u16 = 0;
+const MAGIC: u8 = 0xC7;
+/// The number of entries kept.
+pub const ENTRIES: usize = 8;
+const ENTRY_SIZE: u16 = 4;
+const FIRST_ENTRY_ADDR: u16 = LOG_ADDR + 2;
+
+/// The first EEPROM address after the log, for anything else that needs to store data.
+pub const LOG_END: u16 = FIRST_ENTRY_ADDR + ENTRIES as u16 * ENTRY_SIZE;
+
+/// Erased EEPROM bytes read as 0xFF, so that marks an empty slot.
+const KIND_EMPTY: u8 = 0xFF;
+const KIND_ERROR: u8 = 1;
+const KIND_PANIC: u8 = 2;
+const KIND_ABORT: u8 = 3;
+const KIND_WATCHDOG: u8 = 4;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Entry {
+    /// `main` ended with an error. Holds the code that was blinked.
+    Error(u8),
+    /// A panic, at the given line. The file name is too long to store, so only an 8-bit hash
+    /// of it is kept, which is enough to tell files apart.
+    Panic {
+        line: u16,
+        file_hash: u8,
+    },
+    Abort,
+    /// The watchdog timer reset the device.
+    WatchdogReset,
+}
+
+impl Entry {
+    fn to_bytes(self) -> [u8; ENTRY_SIZE as usize] {
+        match self {
+            Entry::Error(code) => [KIND_ERROR, code, 0, 0],
+            Entry::Panic { line, file_hash } => {
+                [KIND_PANIC, file_hash, line as u8, (line >> 8) as u8]
+            }
+            Entry::Abort => [KIND_ABORT, 0, 0, 0],
+            Entry::WatchdogReset => [KIND_WATCHDOG, 0, 0, 0],
+        }
+    }
+
+    fn from_bytes(bytes: [u8; ENTRY_SIZE as usize]) -> Option<Entry> {
+        match bytes[0] {
+            KIND_ERROR => Some(Entry::Error(bytes[1])),
+            KIND_PANIC => Some(Entry::Panic {
+                line: bytes[2] as u16 | (bytes[3] as u16) << 8,
+                file_hash: bytes[1],
+            }),
+            KIND_ABORT => Some(Entry::Abort),
+            KIND_WATCHDOG => Some(Entry::WatchdogReset),
+            _ => None,
+        }
+    }
+}
+
+/// Hashes a panic's file name for storing in the log.
+pub fn hash_file_name(file: &str) -> u8 {
+    file.bytes().fold(0, |hash: u8, b| hash.rotate_left(3) ^ b)
+}
+
+/// Adds an entry to the log, overwriting the oldest if it's full.
+pub fn record(eeprom: &mut EEPROM, entry: Entry) {
+    if eeprom.read(LOG_ADDR) != MAGIC {
+        clear(eeprom);
+    }
+
+    let next = eeprom.read(LOG_ADDR + 1) as usize % ENTRIES;
+    eeprom.write_bytes(entry_addr(next), &entry.to_bytes());
+    eeprom.write(LOG_ADDR + 1, ((next + 1) % ENTRIES) as u8);
+}
+
+/// Records a watchdog reset if that's why we're starting up.
+///
+/// The reset flags are cleared, as they're kept through other resets. After a watchdog reset
+/// the watchdog is still running, with WDE forced on until WDRF is cleared, so it's turned off
+/// straight away, before the EEPROM writes give it time to reset us again.
+pub fn record_reset_cause(eeprom: &mut EEPROM) {
+    let watchdog_reset = unsafe { MCUSR::get_bit(MCUSR::WDRF) };
+
+    unsafe {
+        MCUSR::set_raw_value(0);
+
+        // WDE can only be cleared within 4 cycles of setting WDCE, so an interrupt can't come
+        // between them.
+        interrupt::free(|_| {
+            WDTCSR::set_value(WDTCSR::WDCE | WDTCSR::WDE);
+            WDTCSR::set_raw_value(0);
+        });
+    }
+
+    if watchdog_reset {
+        record(eeprom, Entry::WatchdogReset);
+    }
+}
+
+/// Empties the log.
+pub fn clear(eeprom: &mut EEPROM) {
+    for i in 0..ENTRIES {
+        eeprom.write(entry_addr(i), KIND_EMPTY);
+    }
+    eeprom.write(LOG_ADDR + 1, 0);
+    eeprom.write(LOG_ADDR, MAGIC);
+}
+
+/// Returns the entries in the log, oldest first.
+pub fn entries(eeprom: &EEPROM) -> [Option<Entry>; ENTRIES] {
+    let mut entries = [None; ENTRIES];
+    if eeprom.read(LOG_ADDR) != MAGIC {
+        return entries;
+    }
+
+    let next = eeprom.read(LOG_ADDR + 1) as usize;
+    for (i, entry) in entries.iter_mut().enumerate() {
+        let mut bytes = [0; ENTRY_SIZE as usize];
+        eeprom.read_bytes(entry_addr((next + i) % ENTRIES), &mut bytes);
+        *entry = Entry::from_bytes(bytes);
+    }
+
+    entries
+}
+
+fn entry_addr(idx: usize) -> u16 {
+    FIRST_ENTRY_ADDR + idx as u16 * ENTRY_SIZE
+}
+
+/// Shows the log on the display, one entry per line, oldest at the top.
+///
+/// Errors show their code, and panics their line (`L`) and file hash (`F`).
+pub fn show(
+    eeprom: &EEPROM,
+    display: &mut Display,
+    twi: &mut twi::TWI,
+) -> Result<(), twi::TWIError> {
+    display.clear_display(twi)?;
+
+    let entries = entries(eeprom);
+    if entries.iter().all(Option::is_none) {
+        display.draw_text(twi, 0, 0, "Crash log empty", false)?;
+    }
+
+    for (row, entry) in entries.iter().filter_map(|e| *e).enumerate() {
+        let page = row as u8;
+
+        match entry {
+            Entry::Error(code) => {
+                let x = display.draw_text(twi, 0, page, "Error ", false)?;
+                display.draw_number(twi, x, page, code as u32, false)?;
+            }
+            Entry::Panic { line, file_hash } => {
+                let x = display.draw_text(twi, 0, page, "Panic L", false)?;
+                let x = display.draw_number(twi, x, page, line as u32, false)?;
+                let x = display.draw_text(twi, x, page, " F", false)?;
+                display.draw_number(twi, x, page, file_hash as u32, false)?;
+            }
+            Entry::Abort => {
+                display.draw_text(twi, 0, page, "Abort", false)?;
+            }
+            Entry::WatchdogReset => {
+                display.draw_text(twi, 0, page, "Watchdog reset", false)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the log over the USART, oldest first.
+pub fn dump(eeprom: &EEPROM, usart: &mut USART) {
+    usart.send("Crash log:\r\n");
+
+    for entry in entries(eeprom).iter().filter_map(|e| *e) {
+        match entry {
+            Entry::Error(code) => {
+                usart.send("error ");
+                usart.send_decimal(code as u16);
+            }
+            Entry::Panic { line, file_hash } => {
+                usart.send("panic at line ");
+                usart.send_decimal(line);
+                usart.send(", file hash ");
+                usart.send_decimal(file_hash as u16);
+            }
+            Entry::Abort => usart.send("abort"),
+            Entry::WatchdogReset => usart.send("watchdog reset"),
+        }
+        usart.send("\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::{eeprom::tests::simulate_eeprom, register::Bit, sim};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn entries_round_trip_through_bytes() {
+        let entries = [
+            Entry::Error(7),
+            Entry::Panic {
+                line: 0x1234,
+                file_hash: 0xAB,
+            },
+            Entry::Abort,
+            Entry::WatchdogReset,
+        ];
+        for &entry in &entries {
+            assert_eq!(Entry::from_bytes(entry.to_bytes()), Some(entry));
+        }
+
+        assert_eq!(Entry::from_bytes([KIND_EMPTY; ENTRY_SIZE as usize]), None);
+        assert_eq!(Entry::from_bytes([0, 1, 2, 3]), None);
+    }
+
+    #[test]
+    fn blank_or_corrupted_log_is_empty() {
+        let _hw = sim::reset();
+        let storage = simulate_eeprom();
+        let mut eeprom = unsafe { EEPROM::steal() };
+
+        assert_eq!(entries(&eeprom), [None; ENTRIES]);
+
+        // Entries left behind by something else aren't read without the magic byte.
+        record(&mut eeprom, Entry::Abort);
+        storage.borrow_mut().0[LOG_ADDR as usize] = 0;
+        assert_eq!(entries(&eeprom), [None; ENTRIES]);
+
+        // The next record starts a fresh log.
+        record(&mut eeprom, Entry::Error(3));
+        let mut expected = [None; ENTRIES];
+        expected[ENTRIES - 1] = Some(Entry::Error(3));
+        assert_eq!(entries(&eeprom), expected);
+    }
+
+    #[test]
+    fn full_log_overwrites_the_oldest() {
+        let _hw = sim::reset();
+        let _storage = simulate_eeprom();
+        let mut eeprom = unsafe { EEPROM::steal() };
+
+        for code in 0..ENTRIES as u8 + 3 {
+            record(&mut eeprom, Entry::Error(code));
+        }
+
+        let entries = entries(&eeprom);
+        for (i, entry) in entries.iter().enumerate() {
+            assert_eq!(*entry, Some(Entry::Error(i as u8 + 3)));
+        }
+
+        clear(&mut eeprom);
+        assert_eq!(super::entries(&eeprom), [None; ENTRIES]);
+    }
+
+    #[test]
+    fn watchdog_reset_is_recorded_and_the_watchdog_stopped() {
+        let _hw = sim::reset();
+        let _storage = simulate_eeprom();
+        let mut eeprom = unsafe { EEPROM::steal() };
+
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let log = writes.clone();
+        sim::on_write::<WDTCSR, _>(move |hw, val| {
+            log.borrow_mut().push(val);
+            hw.data[WDTCSR::ADDR as usize] = val;
+        });
+        sim::set::<MCUSR>(1 << MCUSR::WDRF.bit_id());
+        sim::set::<WDTCSR>(1 << WDTCSR::WDE.bit_id());
+
+        record_reset_cause(&mut eeprom);
+
+        assert_eq!(sim::get::<MCUSR>(), 0);
+        let change_enable = (1 << WDTCSR::WDCE.bit_id()) | (1 << WDTCSR::WDE.bit_id());
+        assert_eq!(*writes.borrow(), [change_enable, 0]);
+
+        let mut expected = [None; ENTRIES];
+        expected[ENTRIES - 1] = Some(Entry::WatchdogReset);
+        assert_eq!(entries(&eeprom), expected);
+
+        // Other resets aren't logged.
+        sim::set::<MCUSR>(1 << MCUSR::EXTRF.bit_id());
+        record_reset_cause(&mut eeprom);
+        assert_eq!(entries(&eeprom), expected);
+    }
+}
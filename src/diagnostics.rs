@@ -0,0 +1,136 @@
+//! Reports fatal errors, so there's something more to go on than a blinking LED.
+//!
+//! By the time we get here, the game may have been in the middle of using the TWI or USART, so
+//! the peripherals are stolen and set up from scratch. The report is a best effort: if the
+//! display doesn't acknowledge its address, or the bus reports an error, we carry on to blinking
+//! the LED. The TWI driver has no timeout though, so a bus that's stuck, such as SDA being held
+//! low, hangs here without blinking.
+//!
+//! The display shows "Panic" with the panic's line number, or "Abort". With the `panic-usart`
+//! feature the whole panic message is also sent over the USART, but it's off by default as the
+//! formatting machinery takes up a lot of flash.
+//!
+//! Both are also recorded in the crash log, so they can be looked at after a power cycle.
+
+use crate::{
+    board::Board,
+    crash_log::{self, Entry},
+    hal::{
+        eeprom::EEPROM,
+        interrupt::{self, Mutex},
+        ports::{Floating, Input, Pin},
+        twi::{TWIError, SCL, SDA, TWI, TWI0},
+        Peripherals,
+    },
+    peripherals::display::Display,
+    settings::Settings,
+    DISPLAY_ADDR,
+};
+use core::{cell::Cell, panic::PanicInfo};
+
+/// Set once a report has started, so a panic while reporting doesn't try again.
+static REPORTING: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+/// Reports a panic, with its location and message.
+pub fn report_panic(info: &PanicInfo) {
+    let mut board = match start_report() {
+        Some(board) => board,
+        None => return,
+    };
+
+    let line = info.location().map_or(0, |l| l.line());
+    let file_hash = info
+        .location()
+        .map_or(0, |l| crash_log::hash_file_name(l.file()));
+    crash_log::record(
+        &mut board.eeprom,
+        Entry::Panic {
+            line: line.min(u16::MAX as u32) as u16,
+            file_hash,
+        },
+    );
+
+    #[cfg(feature = "panic-usart")]
+    {
+        use core::fmt::Write;
+
+        let mut usart = crate::hal::usart::USART::init(board.usart0);
+        let _ = writeln!(usart, "\r\n{}\r", info);
+    }
+
+    let _ = show_on_display(
+        board.twi,
+        board.sda,
+        board.scl,
+        &board.eeprom,
+        "Panic line ",
+        Some(line),
+    );
+}
+
+/// Reports a call to `abort`, which doesn't come with any information.
+pub fn report_abort() {
+    let mut board = match start_report() {
+        Some(board) => board,
+        None => return,
+    };
+
+    crash_log::record(&mut board.eeprom, Entry::Abort);
+
+    #[cfg(feature = "panic-usart")]
+    {
+        let mut usart = crate::hal::usart::USART::init(board.usart0);
+        usart.send("\r\naborted\r\n");
+    }
+
+    let _ = show_on_display(
+        board.twi,
+        board.sda,
+        board.scl,
+        &board.eeprom,
+        "Abort",
+        None,
+    );
+}
+
+/// Takes the peripherals, unless a report is already in progress.
+fn start_report() -> Option<Board> {
+    if interrupt::free(|cs| REPORTING.borrow(cs).replace(true)) {
+        return None;
+    }
+
+    // SAFETY: We never return to whatever was using the peripherals.
+    Some(Board::new(unsafe { Peripherals::steal() }))
+}
+
+fn show_on_display(
+    twi: TWI0,
+    sda: Pin<SDA, Input<Floating>>,
+    scl: Pin<SCL, Input<Floating>>,
+    eeprom: &EEPROM,
+    message: &str,
+    number: Option<u32>,
+) -> Result<(), TWIError> {
+    // Resets the TWI's control register, so an interrupt left enabled by a transfer we
+    // interrupted can't fire once interrupts are back on.
+    let mut twi = TWI::init(twi, sda, scl);
+    twi.set_address(DISPLAY_ADDR)?;
+
+    // The TWI driver needs its interrupt, which may be off if we panicked in a handler or a
+    // critical section.
+    // SAFETY: We never return to the critical section we may have come from.
+    unsafe {
+        interrupt::enable();
+    }
+
+    let mut display = Display::init(&mut twi)?;
+    Settings::load(eeprom).apply(&mut display, &mut twi)?;
+    display.clear_display(&mut twi)?;
+
+    let x = display.draw_text(&mut twi, 0, 0, message, false)?;
+    if let Some(number) = number {
+        display.draw_number(&mut twi, x, 0, number, false)?;
+    }
+
+    Ok(())
+}
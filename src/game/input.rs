@@ -4,33 +4,47 @@
 //! so we'll hide it away in here.
 
 use crate::{
-    hal::{clock::Instant, ports::PortD},
+    board::{ButtonPort, Down, Left, Right, Up},
+    hal::{
+        clock::Instant,
+        ports::{Input as PinInput, Pin, Port, PullUp},
+    },
     peripherals::button::Button,
 };
 
 pub struct Input {
-    left: Button<PortD>,
-    right: Button<PortD>,
-    up: Button<PortD>,
-    down: Button<PortD>,
+    left: Button<Left>,
+    right: Button<Right>,
+    up: Button<Up>,
+    down: Button<Down>,
 }
 
 impl Input {
-    pub fn init() -> Input {
+    pub fn init(
+        left: Pin<Left, PinInput<PullUp>>,
+        right: Pin<Right, PinInput<PullUp>>,
+        down: Pin<Down, PinInput<PullUp>>,
+        up: Pin<Up, PinInput<PullUp>>,
+    ) -> Input {
         Input {
-            left: Button::new(PortD::PD3),
-            right: Button::new(PortD::PD4),
-            down: Button::new(PortD::PD5),
-            up: Button::new(PortD::PD6),
+            left: Button::new(left),
+            right: Button::new(right),
+            down: Button::new(down),
+            up: Button::new(up),
         }
     }
 
     /// Returns whether any of the inputs were pressed.
+    ///
+    /// All the buttons are sampled from a single read of the port, so pressing several
+    /// at once is seen as one event.
     pub fn update(&mut self, now: Instant) -> bool {
-        self.left.update(now);
-        self.right.update(now);
-        self.up.update(now);
-        self.down.update(now);
+        let pins = ButtonPort::read_all();
+
+        self.left.update(pins, now);
+        self.right.update(pins, now);
+        self.up.update(pins, now);
+        self.down.update(pins, now);
 
         self.left() | self.right() | self.up() | self.down()
     }
@@ -5,6 +5,7 @@ pub use input::Input;
 
 use crate::{
     hal::{
+        delay_millis,
         progmem::PGMSlice,
         twi::{TWIError, TWI},
     },
@@ -15,10 +16,13 @@ use crate::{
 const LEVEL_SIZE: usize = 16;
 const NUM_ENEMIES: usize = 10;
 
-const SCREEN_WIDTH: usize = display::WIDTH as usize / 8;
-const SCREEN_HEIGHT: usize = display::HEIGHT as usize / 8;
+const SCREEN_WIDTH: usize = display::COLUMNS as usize;
+const SCREEN_HEIGHT: usize = display::ROWS as usize;
 const SCREEN_MAX_X: usize = LEVEL_SIZE - SCREEN_WIDTH;
 const SCREEN_MAX_Y: usize = LEVEL_SIZE - SCREEN_HEIGHT;
+/// How long each pixel of a scroll stays on the screen. The display refreshes at around
+/// 100Hz, so any less and some steps wouldn't be seen.
+const SCROLL_STEP_MS: u16 = 10;
 
 #[derive(Copy, Clone)]
 pub enum ContinueState {
@@ -40,11 +44,11 @@ pub enum Tile {
 impl Tile {
     pub fn graphic(self) -> PGMSlice {
         match self {
-            Tile::Floor => tiles::FLOOR(),
-            Tile::Wall => tiles::WALL(),
-            Tile::Stairs => tiles::STAIRS(),
-            Tile::Player => tiles::PLAYER(),
-            Tile::Enemy => tiles::ENEMY(),
+            Tile::Floor => tiles::FLOOR.as_slice(),
+            Tile::Wall => tiles::WALL.as_slice(),
+            Tile::Stairs => tiles::STAIRS.as_slice(),
+            Tile::Player => tiles::PLAYER.as_slice(),
+            Tile::Enemy => tiles::ENEMY.as_slice(),
         }
     }
 }
@@ -86,31 +90,17 @@ pub struct Game {
     player_pos: Position,
     enemies: [Option<Enemy>; NUM_ENEMIES],
     level: u8,
+    /// The map row that was at the top of the screen when it was last drawn.
+    top_row: u8,
 }
 
 impl Game {
     pub fn title_screen() -> PGMSlice {
-        tiles::TITLE_SCREEN()
+        tiles::TITLE_SCREEN.as_slice()
     }
 
     pub fn game_over_screen() -> PGMSlice {
-        tiles::GAME_OVER()
-    }
-
-    pub fn get_digit_tile(digit: u8) -> PGMSlice {
-        match digit {
-            b'9' => tiles::N9(),
-            b'8' => tiles::N8(),
-            b'7' => tiles::N7(),
-            b'6' => tiles::N6(),
-            b'5' => tiles::N5(),
-            b'4' => tiles::N4(),
-            b'3' => tiles::N3(),
-            b'2' => tiles::N2(),
-            b'1' => tiles::N1(),
-            b'0' => tiles::N0(),
-            _ => tiles::FLOOR(),
-        }
+        tiles::GAME_OVER.as_slice()
     }
 
     pub fn new() -> Self {
@@ -119,6 +109,7 @@ impl Game {
             level: 0,
             enemies: [None; NUM_ENEMIES],
             map: Map([Tile::Floor; LEVEL_SIZE * LEVEL_SIZE]),
+            top_row: 0,
         }
     }
 
@@ -210,7 +201,7 @@ impl Game {
         ContinueState::Continue
     }
 
-    pub fn draw(&self, display: &mut Display, twi: &mut TWI) -> Result<(), TWIError> {
+    pub fn draw(&mut self, display: &mut Display, twi: &mut TWI) -> Result<(), TWIError> {
         // The map is bigger than the screen, so find the top-left coordinate of the
         // rendered portion. Ensure that the value does not overflow below 0, nor that
         // the bottom or right side run off the map.
@@ -222,42 +213,38 @@ impl Game {
             .saturating_sub(SCREEN_WIDTH / 2)
             .min(SCREEN_MAX_X);
 
-        let rows = self
-            .map
-            .0
-            .chunks_exact(LEVEL_SIZE)
-            .skip(offset_y)
-            .take(SCREEN_HEIGHT);
-
-        display.set_draw_coords(twi, 0, 0)?;
-        for row in rows {
-            for tile in row.iter().skip(offset_x).take(SCREEN_WIDTH) {
-                display.draw_tile(twi, &tile.graphic())?;
+        // When the view moves up or down, the display can scroll what's already on it, leaving
+        // only the rows that come into view to be sent. There's nothing like that for moving
+        // sideways, so then every cell that's changed is sent.
+        let scroll = offset_y as i8 - self.top_row as i8;
+        if scroll != 0 {
+            // A single row is slid into place a pixel at a time. Until it's drawn below, the
+            // row coming into view shows what's moving off the other edge.
+            if scroll == 1 || scroll == -1 {
+                for pixels in 1..8 {
+                    display.set_scroll_offset(twi, pixels * scroll)?;
+                    delay_millis(SCROLL_STEP_MS);
+                }
             }
+            display.scroll_rows(twi, scroll)?;
+            self.top_row = offset_y as u8;
         }
 
-        // Rather than check every single iteration above whether we need to draw
-        // the player and enemies, we'll just re-draw those tiles.
-        display.set_draw_coords(
-            twi,
-            self.player_pos.x - offset_x as u8,
-            self.player_pos.y - offset_y as u8,
-        )?;
-        display.draw_tile(twi, &Tile::Player.graphic())?;
-
-        // The player is always on screen, so no fancy logic was needed. But for the enemies
-        // we need to filter out those that aren't on screen.
-        let enemies = self.enemies.iter().filter_map(Option::as_ref).filter(|e| {
-            (offset_x as u8..(offset_x + SCREEN_WIDTH) as u8).contains(&e.position.x)
-                && (offset_y as u8..(offset_y + SCREEN_HEIGHT) as u8).contains(&e.position.y)
-        });
-        for e in enemies {
-            display.set_draw_coords(
-                twi,
-                e.position.x - offset_x as u8,
-                e.position.y - offset_y as u8,
-            )?;
-            display.draw_tile(twi, &Tile::Enemy.graphic())?;
+        // Only the cells that have changed since the last draw are sent, so every cell is drawn
+        // with whatever should be on it, including the player and enemies.
+        for y in 0..SCREEN_HEIGHT as u8 {
+            for x in 0..SCREEN_WIDTH as u8 {
+                let pos = Position::new(x + offset_x as u8, y + offset_y as u8);
+                let tile = if pos == self.player_pos {
+                    Tile::Player
+                } else if self.enemies.iter().flatten().any(|e| e.position == pos) {
+                    Tile::Enemy
+                } else {
+                    self.map[(pos.x, pos.y)]
+                };
+
+                display.draw_tile_at(twi, x, y, tile as u8, &tile.graphic())?;
+            }
         }
 
         Ok(())
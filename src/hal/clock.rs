@@ -12,175 +12,151 @@
 //! Cannot represent durations of more than 2^16 ms (approx. 65 seconds),
 //! which is enough for this application.
 
-use crate::hal::{register::Register, CPU_FREQ};
-use core::marker::PhantomData;
+use crate::hal::{
+    interrupt::{self, Mutex},
+    register::{Field, Register},
+    CPU_FREQ,
+};
+use core::cell::Cell;
 
 pub mod registers {
-    reg! {
-        /// Timer/Counter 0 Control Register A
-        TCCR0A: u8 {
-            addr: 0x44,
-            write mask: 0b1111_0011,
-            bits: {
-                /// Timer/Counter 0 Waveform Generation Mode - Bit 0
-                WGM00 = 0, RW;
-                /// Timer/Counter 0 Waveform Generation Mode - Bit 1
-                WGM01 = 1, RW;
-                /// Timer/Counter 0 Channel B Compare Output Mode - Bit 0
-                COM0B0 = 4, RW;
-                /// Timer/Counter 0 Channel B Compare Output Mode - Bit 1
-                COM0B1 = 5, RW;
-                /// Timer/Counter 0 Channel A Compare Output Mode - Bit 0
-                COM0A0 = 6, RW;
-                /// Timer/Counter 0 Channel A Compare Output Mode - Bit 1
-                COM0A1 = 7, RW;
-            }
-        }
-    }
-
-    reg! {
-        /// Timer/Counter 0 Control Register B
-        TCCR0B: u8 {
-            addr: 0x45,
-            write mask: 0b1100_1111,
-            bits: {
-                /// Timer/Counter 0 Clock Select - Bit 0
-                CS00 = 0, RW;
-                /// Timer/Counter 0 Clock Select - Bit 1
-                CS01 = 1, RW;
-                /// Timer/Counter 0 Clock Select - Bit 2
-                CS02 = 2, RW;
-                /// Timer/Counter 0 Waveform Generation Mode - Bit 2
-                WGM02 = 3, RW;
-                /// Timer/Counter 0 Force Output Compare B
-                FOC0B = 6, W;
-                /// Timer/Counter 0 Force Output Compare A
-                FOC0A = 7, W;
-            }
+    pub use crate::hal::device::tc0::*;
+
+    field_enum! {
+        /// The clock source for Timer/Counter 0.
+        ClockSelect: u8 {
+            /// No clock source, so the timer is stopped.
+            Stopped = 0,
+            /// CPU clock, no prescaling.
+            Div1 = 1,
+            /// CPU clock / 8.
+            Div8 = 2,
+            /// CPU clock / 64.
+            Div64 = 3,
+            /// CPU clock / 256.
+            Div256 = 4,
+            /// CPU clock / 1024.
+            Div1024 = 5,
+            /// External clock on the T0 pin, clocked on the falling edge.
+            ExternalFalling = 6,
+            /// External clock on the T0 pin, clocked on the rising edge.
+            ExternalRising = 7,
+            _ => Stopped,
         }
     }
+}
+use registers::*;
 
-    reg! {
-        /// Timer/Counter 0 Interrupt Mask Register
-        TIMSK0: u8 {
-            addr: 0x6E,
-            write mask: 0b0000_0111,
-            bits: {
-                /// Timer/Counter 0 Overflow Interrupt Enable
-                TOIE = 0, RW;
-                /// Timer/Counter 0 Output Compare A Match Interrupt Enable
-                OCIEA = 1, RW;
-                /// Timer/Counter 0 Output Compare B Match Interrupt Enable
-                OCIEB = 2, RW;
-            }
-        }
-    }
+/// The CPU cycles in a tick of the clock.
+const fn cycles_per_ms(cpu_freq: u32) -> u32 {
+    cpu_freq / 1000
+}
 
-    reg! {
-        /// Timer/Counter 0 Counter Value Register
-        TCNT0: u8 {
-            addr: 0x46,
-            write mask: 0xFF,
-        }
+/// The prescale setting to use on the timer.
+///
+/// The timer is only 8 bits, so this is the smallest prescale that fits a millisecond's worth
+/// of timer counts in it, which gives the most accurate tick.
+const fn prescale(cpu_freq: u32) -> u32 {
+    let cycles = cycles_per_ms(cpu_freq);
+    if cycles <= 256 {
+        1
+    } else if cycles / 8 <= 256 {
+        8
+    } else if cycles / 64 <= 256 {
+        64
+    } else if cycles / 256 <= 256 {
+        256
+    } else {
+        1024
     }
+}
 
-    reg! {
-        /// Timer/Counter 0 Output Compare Register A
-        OCR0A: u8 {
-            addr: 0x47,
-            write mask: 0xFF,
-        }
+const fn clock_select(cpu_freq: u32) -> ClockSelect {
+    match prescale(cpu_freq) {
+        1 => ClockSelect::Div1,
+        8 => ClockSelect::Div8,
+        64 => ClockSelect::Div64,
+        256 => ClockSelect::Div256,
+        _ => ClockSelect::Div1024,
     }
+}
 
-    reg! {
-        /// Timer/Counter 0 Output Compare Register B
-        OCR0B: u8 {
-            addr: 0x48,
-            write mask: 0xFF,
-        }
-    }
+/// The timer counts in a tick, rounded to the nearest.
+const fn counts_per_ms(cpu_freq: u32) -> u32 {
+    let prescale = prescale(cpu_freq);
+    (cycles_per_ms(cpu_freq) + prescale / 2) / prescale
+}
 
-    reg! {
-        /// Timer/Counter 0 Interrupt Flag Register
-        TIFR0: u8 {
-            addr: 0x35,
-            write mask: 0b0000_0111,
-            bits: {
-                /// Timer/Counter 0 Overflow Flag
-                TOV = 0, RW;
-                /// Timer/Counter 0 Output Compare A Match Flag
-                OCFA = 1, RW;
-                /// Timer/Counter 0 Output Compare B Match Flag
-                OCFB = 2, RW;
-            }
-        }
-    }
+/// The Compare Match A value to use to control the frequency of the interrupt trigger.
+///
+/// In CTC mode the timer counts from 0 up to and including the match value, so it's one
+/// less than the number of counts.
+const fn ocr0a_value(cpu_freq: u32) -> u8 {
+    (counts_per_ms(cpu_freq) - 1) as u8
 }
-use registers::*;
 
-/// The prescale setting we're using on the timer.
-const PRESCALE: u32 = 64;
+const PRESCALE: u32 = prescale(CPU_FREQ);
+const CLOCK_SELECT: ClockSelect = clock_select(CPU_FREQ);
+const COUNTS_PER_MS: u32 = counts_per_ms(CPU_FREQ);
+const OCR0A_VALUE: u8 = ocr0a_value(CPU_FREQ);
 
-/// The Compare Match A value we'll use to control the frequence of the interrupt trigger.
-const OCR0A_VALUE: u8 = (CPU_FREQ / 1000 / PRESCALE) as u8;
+// The CPU is too fast for a millisecond to fit in the timer.
+const_assert!(COUNTS_PER_MS >= 1 && COUNTS_PER_MS <= 256);
+// The tick is more than 1% off a millisecond. Not every frequency divides evenly, but at
+// 20MHz the error is only 0.16%.
+const_assert!(
+    (COUNTS_PER_MS * PRESCALE * 100 > cycles_per_ms(CPU_FREQ) * 99)
+        && (COUNTS_PER_MS * PRESCALE * 100 < cycles_per_ms(CPU_FREQ) * 101)
+);
 
 /// How many milliseconds have passed.
 ///
 /// Because we don't use the absolute value anywhere, only the difference
 /// between two instances, having it be u16 is fine enough for this purpose.
-static mut TICKS: u16 = 0;
+static TICKS: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
 
-/// Keeps track of whether the clock has been initialised.
-static mut HAS_INIT: bool = false;
+/// Ownership token for Timer/Counter 0. Obtained from `Peripherals::take`.
+pub struct TC0 {
+    _p: (),
+}
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub enum ClockError {
-    InitError,
+impl TC0 {
+    /// SAFETY: Only one token for the timer may exist at a time.
+    pub unsafe fn steal() -> Self {
+        Self { _p: () }
+    }
 }
 
 /// A monotonic clock, to track the passing of time.
 ///
-/// Only one instance of this can be alive at any one time.
-pub struct Clock(PhantomData<()>);
+/// Consumes the timer's token, so only one instance of this can be alive at any one time.
+pub struct Clock {
+    _tc0: TC0,
+}
 
 impl Clock {
-    pub fn init() -> Result<Clock, ClockError> {
+    pub fn init(tc0: TC0) -> Clock {
         unsafe {
-            if HAS_INIT {
-                Err(ClockError::InitError)
-            } else {
-                // Setting CTC mode.
-                TCCR0A::set_value(TCCR0A::WGM01);
-                // Enable the OCRA interrupt.
-                TIMSK0::set_bits(TIMSK0::OCIEA);
-
-                TCNT0::set_raw_value(0);
-                OCR0A::set_raw_value(OCR0A_VALUE);
-
-                // Configure the prescaler for 64.
-                TCCR0B::set_value(TCCR0B::CS00 | TCCR0B::CS01);
-                HAS_INIT = true;
-
-                Ok(Clock(PhantomData))
-            }
+            // Setting CTC mode.
+            TCCR0A::set_value(TCCR0A::WGM01);
+            // Enable the OCRA interrupt.
+            TIMSK0::set_bits(TIMSK0::OCIE0A);
+
+            TCNT0::set_raw_value(0);
+            OCR0A::set_raw_value(OCR0A_VALUE);
+
+            // Configure the prescaler, which starts the timer.
+            TCCR0B::set_value(TCCR0B::CS0.with(CLOCK_SELECT));
         }
+
+        Clock { _tc0: tc0 }
     }
 
     pub fn now(&self) -> Instant {
         // Because we're dealing with a multi-byte value which is updated in an interrupt
-        // we need to make certain that we disable the interrupts while we do a volatile
-        // read so that the value isn't updated in the middle of reading the value.
-        unsafe {
-            crate::hal::disable_interrupts();
-
-            let ticks = &mut TICKS as *mut u16;
-            let value = ticks.read_volatile();
-
-            // Don't forget to re-enable interrupts or you break the clock and TWI.
-            crate::hal::enable_interrupts();
-
-            Instant(value)
-        }
+        // we need to make certain that the interrupt can't fire in the middle of reading
+        // the value.
+        interrupt::free(|cs| Instant(TICKS.borrow(cs).get()))
     }
 }
 
@@ -188,11 +164,9 @@ impl Drop for Clock {
     fn drop(&mut self) {
         unsafe {
             // Disable the clock source.
-            TCCR0B::clear_bits(TCCR0B::CS00 | TCCR0B::CS01);
+            TCCR0B::set_field(TCCR0B::CS0, ClockSelect::Stopped);
             // Disable the interrupt.
-            TIMSK0::clear_bits(TIMSK0::OCIEA);
-
-            HAS_INIT = false;
+            TIMSK0::clear_bits(TIMSK0::OCIE0A);
         }
     }
 }
@@ -207,10 +181,63 @@ impl Instant {
     }
 }
 
+interrupt!(TIMER0_COMPA, tick);
+
 /// Timer/Counter 0 Output Compare A Match interrupt.
-#[no_mangle]
-pub unsafe extern "avr-interrupt" fn __vector_14() {
-    // No need to disable interrupts here, as interrupts can't trigger
-    // while in an interrupt handler.
-    TICKS += 1;
+fn tick() {
+    // Interrupts can't trigger while in an interrupt handler, so this won't
+    // re-enable them on the way out.
+    interrupt::free(|cs| {
+        let ticks = TICKS.borrow(cs);
+        ticks.set(ticks.get().wrapping_add(1));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::sim;
+
+    #[test]
+    fn init_sets_up_compare_match() {
+        let _hw = sim::reset();
+        let clock = Clock::init(unsafe { TC0::steal() });
+
+        assert_eq!(sim::get::<TCCR0A>(), 0b0000_0010);
+        assert_eq!(sim::get::<TCCR0B>(), CLOCK_SELECT as u8);
+        assert_eq!(sim::get::<OCR0A>(), OCR0A_VALUE);
+        assert_eq!(sim::get::<TIMSK0>(), 0b0000_0010);
+
+        drop(clock);
+        assert_eq!(sim::get::<TCCR0B>(), 0);
+        assert_eq!(sim::get::<TIMSK0>(), 0);
+    }
+
+    #[test]
+    fn timer_settings_for_each_cpu_frequency() {
+        assert!(clock_select(1_000_000) == ClockSelect::Div8);
+        assert_eq!(ocr0a_value(1_000_000), 124);
+
+        assert!(clock_select(8_000_000) == ClockSelect::Div64);
+        assert_eq!(ocr0a_value(8_000_000), 124);
+
+        assert!(clock_select(16_000_000) == ClockSelect::Div64);
+        assert_eq!(ocr0a_value(16_000_000), 249);
+
+        // 20,000 cycles don't fit in 256 counts at /64, and 78.125 counts at /256 rounds
+        // down, so the tick is 0.16% short.
+        assert!(clock_select(20_000_000) == ClockSelect::Div256);
+        assert_eq!(ocr0a_value(20_000_000), 77);
+    }
+
+    #[test]
+    fn compare_match_ticks_the_clock() {
+        let _hw = sim::reset();
+        let clock = Clock::init(unsafe { TC0::steal() });
+
+        let start = clock.now();
+        tick();
+        tick();
+        assert_eq!(clock.now().elapsed(start), 2);
+    }
 }
@@ -0,0 +1,954 @@
+// Generated by the build.rs file from ATmega2560.atdf during compilation
+
+/// The size of the EEPROM, in bytes.
+pub const EEPROM_SIZE: u16 = 0x1000;
+
+/// The address of the start of the internal RAM.
+pub const RAM_START: u16 = 0x0200;
+/// The size of the internal RAM, in bytes.
+pub const RAM_SIZE: u16 = 0x2000;
+
+pub mod cpu {
+    reg! {
+        /// MCU Status Register
+        MCUSR: u8 {
+            addr: 0x54,
+            write mask: 0x1F,
+            bits: {
+                /// Power-on Reset Flag
+                PORF = 0, RW;
+                /// External Reset Flag
+                EXTRF = 1, RW;
+                /// Brown-out Reset Flag
+                BORF = 2, RW;
+                /// Watchdog Reset Flag
+                WDRF = 3, RW;
+                /// JTAG Reset Flag
+                JTRF = 4, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// Extended Z-pointer Register for ELPM/SPM
+        RAMPZ: u8 {
+            addr: 0x5B,
+            write mask: 0x03,
+        }
+    }
+
+    reg! {
+        /// Extended Indirect Register
+        EIND: u8 {
+            addr: 0x5C,
+            write mask: 0x01,
+        }
+    }
+
+    reg! {
+        /// Stack Pointer
+        SP: u16 {
+            addr: 0x5D,
+            write mask: 0xFFFF,
+        }
+    }
+
+    reg! {
+        /// Status Register
+        SREG: u8 {
+            addr: 0x5F,
+            write mask: 0xFF,
+            bits: {
+                /// Carry Flag
+                C = 0, RW;
+                /// Zero Flag
+                Z = 1, RW;
+                /// Negative Flag
+                N = 2, RW;
+                /// Two's Complement Overflow Flag
+                V = 3, RW;
+                /// Sign Bit
+                S = 4, RW;
+                /// Half Carry Flag
+                H = 5, RW;
+                /// Bit Copy Storage
+                T = 6, RW;
+                /// Global Interrupt Enable
+                I = 7, RW;
+            }
+        }
+    }
+}
+
+pub mod eeprom {
+    reg! {
+        /// EEPROM Control Register
+        EECR: u8 {
+            addr: 0x3F,
+            write mask: 0x3F,
+            bits: {
+                /// EEPROM Read Enable
+                EERE = 0, RW;
+                /// EEPROM Write Enable
+                EEPE = 1, RW;
+                /// EEPROM Master Write Enable
+                EEMPE = 2, RW;
+                /// EEPROM Ready Interrupt Enable
+                EERIE = 3, RW;
+                /// EEPROM Programming Mode Bits - Bit 0
+                EEPM0 = 4, RW;
+                /// EEPROM Programming Mode Bits - Bit 1
+                EEPM1 = 5, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// EEPROM Data Register
+        EEDR: u8 {
+            addr: 0x40,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// EEPROM Address Register
+        EEAR: u16 {
+            addr: 0x41,
+            write mask: 0x0FFF,
+        }
+    }
+}
+
+pub mod porta {
+    reg! {
+        /// Port A Input Pins
+        PINA: u8 {
+            addr: 0x20,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port A Data Direction Register
+        DDRA: u8 {
+            addr: 0x21,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port A Data Register
+        PORTA: u8 {
+            addr: 0x22,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portb {
+    reg! {
+        /// Port B Input Pins
+        PINB: u8 {
+            addr: 0x23,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port B Data Direction Register
+        DDRB: u8 {
+            addr: 0x24,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port B Data Register
+        PORTB: u8 {
+            addr: 0x25,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portc {
+    reg! {
+        /// Port C Input Pins
+        PINC: u8 {
+            addr: 0x26,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port C Data Direction Register
+        DDRC: u8 {
+            addr: 0x27,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port C Data Register
+        PORTC: u8 {
+            addr: 0x28,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portd {
+    reg! {
+        /// Port D Input Pins
+        PIND: u8 {
+            addr: 0x29,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port D Data Direction Register
+        DDRD: u8 {
+            addr: 0x2A,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port D Data Register
+        PORTD: u8 {
+            addr: 0x2B,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod porte {
+    reg! {
+        /// Port E Input Pins
+        PINE: u8 {
+            addr: 0x2C,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port E Data Direction Register
+        DDRE: u8 {
+            addr: 0x2D,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port E Data Register
+        PORTE: u8 {
+            addr: 0x2E,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portf {
+    reg! {
+        /// Port F Input Pins
+        PINF: u8 {
+            addr: 0x2F,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port F Data Direction Register
+        DDRF: u8 {
+            addr: 0x30,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port F Data Register
+        PORTF: u8 {
+            addr: 0x31,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portg {
+    reg! {
+        /// Port G Input Pins
+        PING: u8 {
+            addr: 0x32,
+            write mask: 0x3F,
+        }
+    }
+
+    reg! {
+        /// Port G Data Direction Register
+        DDRG: u8 {
+            addr: 0x33,
+            write mask: 0x3F,
+        }
+    }
+
+    reg! {
+        /// Port G Data Register
+        PORTG: u8 {
+            addr: 0x34,
+            write mask: 0x3F,
+        }
+    }
+}
+
+pub mod porth {
+    reg! {
+        /// Port H Input Pins
+        PINH: u8 {
+            addr: 0x100,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port H Data Direction Register
+        DDRH: u8 {
+            addr: 0x101,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port H Data Register
+        PORTH: u8 {
+            addr: 0x102,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portj {
+    reg! {
+        /// Port J Input Pins
+        PINJ: u8 {
+            addr: 0x103,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port J Data Direction Register
+        DDRJ: u8 {
+            addr: 0x104,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port J Data Register
+        PORTJ: u8 {
+            addr: 0x105,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portk {
+    reg! {
+        /// Port K Input Pins
+        PINK: u8 {
+            addr: 0x106,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port K Data Direction Register
+        DDRK: u8 {
+            addr: 0x107,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port K Data Register
+        PORTK: u8 {
+            addr: 0x108,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod portl {
+    reg! {
+        /// Port L Input Pins
+        PINL: u8 {
+            addr: 0x109,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port L Data Direction Register
+        DDRL: u8 {
+            addr: 0x10A,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Port L Data Register
+        PORTL: u8 {
+            addr: 0x10B,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod tc0 {
+    reg! {
+        /// Timer/Counter0 Interrupt Flag register
+        TIFR0: u8 {
+            addr: 0x35,
+            write mask: 0x07,
+            bits: {
+                /// Timer/Counter0 Overflow Flag
+                TOV0 = 0, RW;
+                /// Timer/Counter0 Output Compare Flag 0A
+                OCF0A = 1, RW;
+                /// Timer/Counter0 Output Compare Flag 0B
+                OCF0B = 2, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// Timer/Counter0 Control Register A
+        TCCR0A: u8 {
+            addr: 0x44,
+            write mask: 0xF3,
+            bits: {
+                /// Waveform Generation Mode - Bit 0
+                WGM00 = 0, RW;
+                /// Waveform Generation Mode - Bit 1
+                WGM01 = 1, RW;
+                /// Compare Output Mode B - Bit 0
+                COM0B0 = 4, RW;
+                /// Compare Output Mode B - Bit 1
+                COM0B1 = 5, RW;
+                /// Compare Output Mode A - Bit 0
+                COM0A0 = 6, RW;
+                /// Compare Output Mode A - Bit 1
+                COM0A1 = 7, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// Timer/Counter0 Control Register B
+        TCCR0B: u8 {
+            addr: 0x45,
+            write mask: 0xCF,
+            bits: {
+                /// Clock Select - Bit 0
+                CS00 = 0, RW;
+                /// Clock Select - Bit 1
+                CS01 = 1, RW;
+                /// Clock Select - Bit 2
+                CS02 = 2, RW;
+                /// Waveform Generation Mode
+                WGM02 = 3, RW;
+                /// Force Output Compare B
+                FOC0B = 6, W;
+                /// Force Output Compare A
+                FOC0A = 7, W;
+            }
+            fields: {
+                /// Clock Select
+                CS0 = 0..=2 => crate::hal::clock::registers::ClockSelect, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// Timer/Counter0
+        TCNT0: u8 {
+            addr: 0x46,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Timer/Counter0 Output Compare Register
+        OCR0A: u8 {
+            addr: 0x47,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Timer/Counter0 Output Compare Register
+        OCR0B: u8 {
+            addr: 0x48,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// Timer/Counter0 Interrupt Mask Register
+        TIMSK0: u8 {
+            addr: 0x6E,
+            write mask: 0x07,
+            bits: {
+                /// Timer/Counter0 Overflow Interrupt Enable
+                TOIE0 = 0, RW;
+                /// Timer/Counter0 Output Compare Match A Interrupt Enable
+                OCIE0A = 1, RW;
+                /// Timer/Counter0 Output Compare Match B Interrupt Enable
+                OCIE0B = 2, RW;
+            }
+        }
+    }
+}
+
+pub mod twi {
+    reg! {
+        /// TWI Bit Rate register
+        TWBR: u8 {
+            addr: 0xB8,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// TWI Status Register
+        TWSR: u8 {
+            addr: 0xB9,
+            write mask: 0x03,
+            bits: {
+                /// TWI Prescaler - Bit 0
+                TWPS0 = 0, RW;
+                /// TWI Prescaler - Bit 1
+                TWPS1 = 1, RW;
+                /// TWI Status - Bit 0
+                TWS0 = 3, R;
+                /// TWI Status - Bit 1
+                TWS1 = 4, R;
+                /// TWI Status - Bit 2
+                TWS2 = 5, R;
+                /// TWI Status - Bit 3
+                TWS3 = 6, R;
+                /// TWI Status - Bit 4
+                TWS4 = 7, R;
+            }
+            fields: {
+                /// TWI Prescaler
+                TWPS = 0..=1 => crate::hal::twi::registers::TWIPrescaler, RW;
+                /// TWI Status
+                TWS = 3..=7 => crate::hal::twi::registers::TWSRStatus, R;
+            }
+        }
+    }
+
+    reg! {
+        /// TWI (Slave) Address register
+        TWAR: u8 {
+            addr: 0xBA,
+            write mask: 0xFF,
+            bits: {
+                /// TWI General Call Recognition Enable Bit
+                TWGCE = 0, RW;
+                /// TWI (Slave) Address register Bits - Bit 0
+                TWA0 = 1, RW;
+                /// TWI (Slave) Address register Bits - Bit 1
+                TWA1 = 2, RW;
+                /// TWI (Slave) Address register Bits - Bit 2
+                TWA2 = 3, RW;
+                /// TWI (Slave) Address register Bits - Bit 3
+                TWA3 = 4, RW;
+                /// TWI (Slave) Address register Bits - Bit 4
+                TWA4 = 5, RW;
+                /// TWI (Slave) Address register Bits - Bit 5
+                TWA5 = 6, RW;
+                /// TWI (Slave) Address register Bits - Bit 6
+                TWA6 = 7, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// TWI Data register
+        TWDR: u8 {
+            addr: 0xBB,
+            write mask: 0xFF,
+        }
+    }
+
+    reg! {
+        /// TWI Control Register
+        TWCR: u8 {
+            addr: 0xBC,
+            write mask: 0xF5,
+            bits: {
+                /// TWI Interrupt Enable
+                TWIE = 0, RW;
+                /// TWI Enable Bit
+                TWEN = 2, RW;
+                /// TWI Write Collision Flag
+                TWWC = 3, R;
+                /// TWI Stop Condition Bit
+                TWSTO = 4, RW;
+                /// TWI Start Condition Bit
+                TWSTA = 5, RW;
+                /// TWI Enable Acknowledge Bit
+                TWEA = 6, RW;
+                /// TWI Interrupt Flag
+                TWINT = 7, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// TWI (Slave) Address Mask Register
+        TWAMR: u8 {
+            addr: 0xBD,
+            write mask: 0xFE,
+            bits: {
+                /// TWI Address Mask - Bit 0
+                TWAM0 = 1, RW;
+                /// TWI Address Mask - Bit 1
+                TWAM1 = 2, RW;
+                /// TWI Address Mask - Bit 2
+                TWAM2 = 3, RW;
+                /// TWI Address Mask - Bit 3
+                TWAM3 = 4, RW;
+                /// TWI Address Mask - Bit 4
+                TWAM4 = 5, RW;
+                /// TWI Address Mask - Bit 5
+                TWAM5 = 6, RW;
+                /// TWI Address Mask - Bit 6
+                TWAM6 = 7, RW;
+            }
+        }
+    }
+}
+
+pub mod usart0 {
+    reg! {
+        /// USART Control and Status Register A
+        UCSR0A: u8 {
+            addr: 0xC0,
+            write mask: 0x43,
+            bits: {
+                /// Multi-processor Communication Mode
+                MPCM0 = 0, RW;
+                /// Double the USART transmission speed
+                U2X0 = 1, RW;
+                /// Parity Error
+                UPE0 = 2, R;
+                /// Data overRun
+                DOR0 = 3, R;
+                /// Framing Error
+                FE0 = 4, R;
+                /// USART Data Register Empty
+                UDRE0 = 5, R;
+                /// USART Transmit Complete
+                TXC0 = 6, RW;
+                /// USART Receive Complete
+                RXC0 = 7, R;
+            }
+        }
+    }
+
+    reg! {
+        /// USART Control and Status Register B
+        UCSR0B: u8 {
+            addr: 0xC1,
+            write mask: 0xFD,
+            bits: {
+                /// Transmit Data Bit 8
+                TXB80 = 0, RW;
+                /// Receive Data Bit 8
+                RXB80 = 1, R;
+                /// Character Size
+                UCSZ02 = 2, RW;
+                /// Transmitter Enable
+                TXEN0 = 3, RW;
+                /// Receiver Enable
+                RXEN0 = 4, RW;
+                /// USART Data register Empty Interrupt Enable
+                UDRIE0 = 5, RW;
+                /// TX Complete Interrupt Enable
+                TXCIE0 = 6, RW;
+                /// RX Complete Interrupt Enable
+                RXCIE0 = 7, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// USART Control and Status Register C
+        UCSR0C: u8 {
+            addr: 0xC2,
+            write mask: 0xFF,
+            bits: {
+                /// Clock Polarity
+                UCPOL0 = 0, RW;
+                /// Character Size - Bit 0
+                UCSZ00 = 1, RW;
+                /// Character Size - Bit 1
+                UCSZ01 = 2, RW;
+                /// Stop Bit Select
+                USBS0 = 3, RW;
+                /// Parity Mode Bits - Bit 0
+                UPM00 = 4, RW;
+                /// Parity Mode Bits - Bit 1
+                UPM01 = 5, RW;
+                /// USART Mode Select - Bit 0
+                UMSEL00 = 6, RW;
+                /// USART Mode Select - Bit 1
+                UMSEL01 = 7, RW;
+            }
+            fields: {
+                /// Parity Mode Bits
+                UPM0 = 4..=5 => crate::hal::usart::registers::ParityMode, RW;
+                /// USART Mode Select
+                UMSEL0 = 6..=7 => crate::hal::usart::registers::USARTMode, RW;
+            }
+        }
+    }
+
+    reg! {
+        /// USART Baud Rate Register
+        UBRR0: u16 {
+            addr: 0xC4,
+            write mask: 0x0FFF,
+        }
+    }
+
+    reg! {
+        /// USART I/O Data Register
+        UDR0: u8 {
+            addr: 0xC6,
+            write mask: 0xFF,
+        }
+    }
+}
+
+pub mod wdt {
+    reg! {
+        /// Watchdog Timer Control Register
+        WDTCSR: u8 {
+            addr: 0x60,
+            write mask: 0xFF,
+            bits: {
+                /// Watchdog Timer Prescaler Bits - Bit 0
+                WDP0 = 0, RW;
+                /// Watchdog Timer Prescaler Bits - Bit 1
+                WDP1 = 1, RW;
+                /// Watchdog Timer Prescaler Bits - Bit 2
+                WDP2 = 2, RW;
+                /// Watch Dog Enable
+                WDE = 3, RW;
+                /// Watchdog Change Enable
+                WDCE = 4, RW;
+                /// Watchdog Timer Prescaler Bits - Bit 3
+                WDP3 = 5, RW;
+                /// Watchdog Timeout Interrupt Enable
+                WDIE = 6, RW;
+                /// Watchdog Timeout Interrupt Flag
+                WDIF = 7, RW;
+            }
+        }
+    }
+}
+
+/// Registers a function as the handler for an interrupt vector, named as in the ATmega2560
+/// datasheet.
+///
+/// ```
+/// interrupt!(TIMER0_COMPA, tick);
+///
+/// fn tick() {
+///     // ...
+/// }
+/// ```
+///
+/// Using a name that isn't in the vector table fails to compile, as does registering two
+/// handlers for the same vector, as both would define the same symbol.
+#[macro_export]
+macro_rules! interrupt {
+    (INT0, $handler:path) => {
+        $crate::isr!(__vector_1, $handler);
+    };
+    (INT1, $handler:path) => {
+        $crate::isr!(__vector_2, $handler);
+    };
+    (INT2, $handler:path) => {
+        $crate::isr!(__vector_3, $handler);
+    };
+    (INT3, $handler:path) => {
+        $crate::isr!(__vector_4, $handler);
+    };
+    (INT4, $handler:path) => {
+        $crate::isr!(__vector_5, $handler);
+    };
+    (INT5, $handler:path) => {
+        $crate::isr!(__vector_6, $handler);
+    };
+    (INT6, $handler:path) => {
+        $crate::isr!(__vector_7, $handler);
+    };
+    (INT7, $handler:path) => {
+        $crate::isr!(__vector_8, $handler);
+    };
+    (PCINT0, $handler:path) => {
+        $crate::isr!(__vector_9, $handler);
+    };
+    (PCINT1, $handler:path) => {
+        $crate::isr!(__vector_10, $handler);
+    };
+    (PCINT2, $handler:path) => {
+        $crate::isr!(__vector_11, $handler);
+    };
+    (WDT, $handler:path) => {
+        $crate::isr!(__vector_12, $handler);
+    };
+    (TIMER2_COMPA, $handler:path) => {
+        $crate::isr!(__vector_13, $handler);
+    };
+    (TIMER2_COMPB, $handler:path) => {
+        $crate::isr!(__vector_14, $handler);
+    };
+    (TIMER2_OVF, $handler:path) => {
+        $crate::isr!(__vector_15, $handler);
+    };
+    (TIMER1_CAPT, $handler:path) => {
+        $crate::isr!(__vector_16, $handler);
+    };
+    (TIMER1_COMPA, $handler:path) => {
+        $crate::isr!(__vector_17, $handler);
+    };
+    (TIMER1_COMPB, $handler:path) => {
+        $crate::isr!(__vector_18, $handler);
+    };
+    (TIMER1_COMPC, $handler:path) => {
+        $crate::isr!(__vector_19, $handler);
+    };
+    (TIMER1_OVF, $handler:path) => {
+        $crate::isr!(__vector_20, $handler);
+    };
+    (TIMER0_COMPA, $handler:path) => {
+        $crate::isr!(__vector_21, $handler);
+    };
+    (TIMER0_COMPB, $handler:path) => {
+        $crate::isr!(__vector_22, $handler);
+    };
+    (TIMER0_OVF, $handler:path) => {
+        $crate::isr!(__vector_23, $handler);
+    };
+    (SPI_STC, $handler:path) => {
+        $crate::isr!(__vector_24, $handler);
+    };
+    (USART0_RX, $handler:path) => {
+        $crate::isr!(__vector_25, $handler);
+    };
+    (USART0_UDRE, $handler:path) => {
+        $crate::isr!(__vector_26, $handler);
+    };
+    (USART0_TX, $handler:path) => {
+        $crate::isr!(__vector_27, $handler);
+    };
+    (ANALOG_COMP, $handler:path) => {
+        $crate::isr!(__vector_28, $handler);
+    };
+    (ADC, $handler:path) => {
+        $crate::isr!(__vector_29, $handler);
+    };
+    (EE_READY, $handler:path) => {
+        $crate::isr!(__vector_30, $handler);
+    };
+    (TIMER3_CAPT, $handler:path) => {
+        $crate::isr!(__vector_31, $handler);
+    };
+    (TIMER3_COMPA, $handler:path) => {
+        $crate::isr!(__vector_32, $handler);
+    };
+    (TIMER3_COMPB, $handler:path) => {
+        $crate::isr!(__vector_33, $handler);
+    };
+    (TIMER3_COMPC, $handler:path) => {
+        $crate::isr!(__vector_34, $handler);
+    };
+    (TIMER3_OVF, $handler:path) => {
+        $crate::isr!(__vector_35, $handler);
+    };
+    (USART1_RX, $handler:path) => {
+        $crate::isr!(__vector_36, $handler);
+    };
+    (USART1_UDRE, $handler:path) => {
+        $crate::isr!(__vector_37, $handler);
+    };
+    (USART1_TX, $handler:path) => {
+        $crate::isr!(__vector_38, $handler);
+    };
+    (TWI, $handler:path) => {
+        $crate::isr!(__vector_39, $handler);
+    };
+    (SPM_READY, $handler:path) => {
+        $crate::isr!(__vector_40, $handler);
+    };
+    (TIMER4_CAPT, $handler:path) => {
+        $crate::isr!(__vector_41, $handler);
+    };
+    (TIMER4_COMPA, $handler:path) => {
+        $crate::isr!(__vector_42, $handler);
+    };
+    (TIMER4_COMPB, $handler:path) => {
+        $crate::isr!(__vector_43, $handler);
+    };
+    (TIMER4_COMPC, $handler:path) => {
+        $crate::isr!(__vector_44, $handler);
+    };
+    (TIMER4_OVF, $handler:path) => {
+        $crate::isr!(__vector_45, $handler);
+    };
+    (TIMER5_CAPT, $handler:path) => {
+        $crate::isr!(__vector_46, $handler);
+    };
+    (TIMER5_COMPA, $handler:path) => {
+        $crate::isr!(__vector_47, $handler);
+    };
+    (TIMER5_COMPB, $handler:path) => {
+        $crate::isr!(__vector_48, $handler);
+    };
+    (TIMER5_COMPC, $handler:path) => {
+        $crate::isr!(__vector_49, $handler);
+    };
+    (TIMER5_OVF, $handler:path) => {
+        $crate::isr!(__vector_50, $handler);
+    };
+    (USART2_RX, $handler:path) => {
+        $crate::isr!(__vector_51, $handler);
+    };
+    (USART2_UDRE, $handler:path) => {
+        $crate::isr!(__vector_52, $handler);
+    };
+    (USART2_TX, $handler:path) => {
+        $crate::isr!(__vector_53, $handler);
+    };
+    (USART3_RX, $handler:path) => {
+        $crate::isr!(__vector_54, $handler);
+    };
+    (USART3_UDRE, $handler:path) => {
+        $crate::isr!(__vector_55, $handler);
+    };
+    (USART3_TX, $handler:path) => {
+        $crate::isr!(__vector_56, $handler);
+    };
+
+    ($vector:ident, $handler:path) => {
+        compile_error!(concat!("Unknown interrupt vector: ", stringify!($vector)));
+    };
+}
@@ -0,0 +1,52 @@
+//! The register definitions for the device, generated from its ATDF file by build.rs.
+//!
+//! Each register group is a module named after the group (`portb`, `tc0`, `twi`, etc.). The
+//! drivers re-export their groups from their own `registers` modules, along with the types
+//! for any multi-bit fields.
+//!
+//! The device is selected with one of the MCU features, which must match the target spec
+//! being built for.
+
+#[cfg(not(any(
+    feature = "atmega328p",
+    feature = "atmega168",
+    feature = "atmega32u4",
+    feature = "atmega2560"
+)))]
+compile_error!("No microcontroller selected. Enable one of the MCU features, such as atmega328p.");
+
+#[cfg(any(
+    all(feature = "atmega328p", feature = "atmega168"),
+    all(feature = "atmega328p", feature = "atmega32u4"),
+    all(feature = "atmega328p", feature = "atmega2560"),
+    all(feature = "atmega168", feature = "atmega32u4"),
+    all(feature = "atmega168", feature = "atmega2560"),
+    all(feature = "atmega32u4", feature = "atmega2560"),
+))]
+compile_error!(
+    "More than one microcontroller selected. Use --no-default-features when selecting one."
+);
+
+#[cfg(feature = "atmega328p")]
+#[macro_use]
+mod atmega328p;
+#[cfg(feature = "atmega328p")]
+pub use atmega328p::*;
+
+#[cfg(feature = "atmega168")]
+#[macro_use]
+mod atmega168;
+#[cfg(feature = "atmega168")]
+pub use atmega168::*;
+
+#[cfg(feature = "atmega32u4")]
+#[macro_use]
+mod atmega32u4;
+#[cfg(feature = "atmega32u4")]
+pub use atmega32u4::*;
+
+#[cfg(feature = "atmega2560")]
+#[macro_use]
+mod atmega2560;
+#[cfg(feature = "atmega2560")]
+pub use atmega2560::*;
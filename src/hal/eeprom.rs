@@ -0,0 +1,138 @@
+//! Reading and writing the EEPROM, for data that needs to survive a power cycle.
+//!
+//! Each byte can only be written around 100,000 times, so writes are skipped if the byte
+//! already holds the value. A write takes about 3.4ms, and waits for the previous one to
+//! finish first.
+
+#![allow(dead_code)]
+use crate::hal::{interrupt, register::Register};
+
+pub mod registers {
+    pub use crate::hal::device::eeprom::*;
+}
+use registers::*;
+
+pub use crate::hal::device::EEPROM_SIZE as SIZE;
+
+/// Ownership token for the EEPROM. Obtained from `Peripherals::take`.
+pub struct EEPROM {
+    _p: (),
+}
+
+impl EEPROM {
+    /// SAFETY: Only one token for the EEPROM may exist at a time.
+    pub unsafe fn steal() -> Self {
+        Self { _p: () }
+    }
+
+    /// Reads a single byte. Panics if the address is past the end of the EEPROM.
+    pub fn read(&self, addr: u16) -> u8 {
+        assert!(addr < SIZE, "EEPROM address out of range");
+
+        unsafe {
+            wait_for_write();
+            EEAR::set_raw_value(addr);
+            EECR::set_value(EECR::EERE);
+            EEDR::get_value()
+        }
+    }
+
+    /// Writes a single byte, unless it already holds the value. Panics if the address is past
+    /// the end of the EEPROM.
+    pub fn write(&mut self, addr: u16, val: u8) {
+        if self.read(addr) == val {
+            return;
+        }
+
+        unsafe {
+            EEDR::set_raw_value(val);
+
+            // EEPE must be set within 4 cycles of EEMPE, so an interrupt can't come between
+            // them. EEAR was set by the read above, and EEPM is left as erase and write.
+            interrupt::free(|_| {
+                EECR::set_value(EECR::EEMPE);
+                EECR::set_value(EECR::EEMPE | EECR::EEPE);
+            });
+        }
+    }
+
+    /// Fills `buf` with the bytes starting at `addr`.
+    pub fn read_bytes(&self, addr: u16, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = self.read(addr + i as u16);
+        }
+    }
+
+    /// Writes `data` to the bytes starting at `addr`.
+    pub fn write_bytes(&mut self, addr: u16, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            self.write(addr + i as u16, byte);
+        }
+    }
+}
+
+/// The EEPROM can't be accessed until the previous write has finished.
+unsafe fn wait_for_write() {
+    while EECR::get_bit(EECR::EEPE) {}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hal::{register::Bit, sim};
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Simulates the EEPROM's storage, returning it and the number of writes done.
+    pub(crate) fn simulate_eeprom() -> Rc<RefCell<(Vec<u8>, usize)>> {
+        let storage = Rc::new(RefCell::new((vec![0xFF; SIZE as usize], 0)));
+        let eeprom = storage.clone();
+
+        sim::on_write::<EECR, _>(move |hw, val| {
+            let bit = |b: u8| val & (1 << b) != 0;
+            let addr = hw.get::<EEAR>() as usize;
+            let mut eeprom = eeprom.borrow_mut();
+
+            if bit(EECR::EERE.bit_id()) {
+                let byte = eeprom.0[addr];
+                hw.set::<EEDR>(byte);
+            }
+            if bit(EECR::EEPE.bit_id()) && bit(EECR::EEMPE.bit_id()) {
+                eeprom.0[addr] = hw.get::<EEDR>();
+                eeprom.1 += 1;
+            }
+
+            // The read and write finish straight away.
+            let done = (1 << EECR::EERE.bit_id()) | (1 << EECR::EEPE.bit_id());
+            hw.data[EECR::ADDR as usize] = val & !done;
+        });
+
+        storage
+    }
+
+    #[test]
+    fn writes_are_read_back() {
+        let _hw = sim::reset();
+        let storage = simulate_eeprom();
+        let mut eeprom = unsafe { EEPROM::steal() };
+
+        eeprom.write_bytes(0x10, &[1, 2, 3]);
+        let mut buf = [0; 3];
+        eeprom.read_bytes(0x10, &mut buf);
+
+        assert_eq!(buf, [1, 2, 3]);
+        assert_eq!(storage.borrow().0[0x11], 2);
+        assert_eq!(eeprom.read(0x13), 0xFF);
+    }
+
+    #[test]
+    fn unchanged_bytes_are_not_written() {
+        let _hw = sim::reset();
+        let storage = simulate_eeprom();
+        let mut eeprom = unsafe { EEPROM::steal() };
+
+        eeprom.write_bytes(0, &[5, 0xFF]);
+        eeprom.write(0, 5);
+
+        assert_eq!(storage.borrow().1, 1);
+    }
+}
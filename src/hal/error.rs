@@ -0,0 +1,22 @@
+//! The fatal errors that get blinked on the LED.
+//!
+//! Each variant has a fixed code, given by `Error::code`. The codes are listed in
+//! `build/errors.rs`, which generates `code` and the table in `error_codes.md`, so a new variant
+//! won't compile until it's been given one.
+
+use crate::hal::twi::TWIError;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// `abort` was called.
+    Abort,
+    /// An interrupt fired that doesn't have a handler.
+    UnexpectedInterrupt,
+    TWI(TWIError),
+}
+
+impl From<TWIError> for Error {
+    fn from(e: TWIError) -> Self {
+        Error::TWI(e)
+    }
+}
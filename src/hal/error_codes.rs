@@ -0,0 +1,19 @@
+// Generated by the build.rs file from build/errors.rs during compilation
+
+use super::{twi::TWIError, Error};
+
+impl Error {
+    /// The number blinked on the LED for this error.
+    pub fn code(self) -> u8 {
+        match self {
+            Error::Abort => 0,
+            Error::TWI(TWIError::BufferLenError) => 1,
+            Error::TWI(TWIError::SendAddressNACK) => 2,
+            Error::TWI(TWIError::SendDataNACK) => 3,
+            Error::TWI(TWIError::NotReady) => 4,
+            Error::TWI(TWIError::BusError) => 5,
+            Error::TWI(TWIError::InvalidAddress) => 7,
+            Error::UnexpectedInterrupt => 10,
+        }
+    }
+}
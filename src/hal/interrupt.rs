@@ -0,0 +1,149 @@
+//! Global interrupt control, and a safe way of sharing data with interrupt handlers.
+//!
+//! Simply disabling and then re-enabling interrupts around an access isn't enough, as if
+//! interrupts were already disabled (such as inside an interrupt handler) they'd be turned
+//! back on behind the caller's back. Instead, `free` checks the global interrupt flag in
+//! SREG first, and only turns interrupts back on if they were on to begin with.
+//!
+//! Data shared with an interrupt handler lives in a `Mutex`, which can only be accessed with
+//! the `CriticalSection` token handed out by `free`.
+
+use crate::hal::register::Register;
+use core::cell::UnsafeCell;
+
+pub mod registers {
+    pub use crate::hal::device::cpu::SREG;
+}
+use registers::*;
+
+/// Enables interrupts globally.
+///
+/// SAFETY: Must not be called inside a critical section, as it would end the critical
+/// section early.
+#[inline(always)]
+pub unsafe fn enable() {
+    // The memory clobber stops the compiler from moving memory accesses across this.
+    #[cfg(not(test))]
+    llvm_asm! {
+        "sei"
+        :
+        :
+        : "memory"
+        : "volatile"
+    }
+
+    #[cfg(test)]
+    SREG::set_bits(SREG::I);
+}
+
+/// Disables interrupts globally.
+#[inline(always)]
+pub fn disable() {
+    #[cfg(not(test))]
+    unsafe {
+        llvm_asm! {
+            "cli"
+            :
+            :
+            : "memory"
+            : "volatile"
+        }
+    }
+
+    #[cfg(test)]
+    unsafe {
+        SREG::clear_bits(SREG::I);
+    }
+}
+
+/// A token proving that interrupts are disabled.
+///
+/// Can only be obtained through a reference passed into the closure given to `free`, so it
+/// can't outlive the critical section.
+pub struct CriticalSection {
+    _p: (),
+}
+
+/// Executes the closure with interrupts disabled, then restores the global interrupt flag
+/// to what it was before.
+///
+/// Only the interrupt flag is restored, not the whole of SREG, otherwise we'd be stomping on
+/// the status flags set by the closure.
+#[inline(always)]
+pub fn free<F, R>(f: F) -> R
+where
+    F: FnOnce(&CriticalSection) -> R,
+{
+    let were_enabled = unsafe { SREG::get_bit(SREG::I) };
+    disable();
+
+    let ret = f(&CriticalSection { _p: () });
+
+    if were_enabled {
+        // SAFETY: Interrupts were enabled when this critical section started, so we can't
+        // be inside another one.
+        unsafe {
+            enable();
+        }
+    }
+
+    ret
+}
+
+/// A container for data shared between interrupt handlers and normal code.
+///
+/// The data can only be accessed while interrupts are disabled. Only shared access is
+/// given, so the data should be wrapped in a `Cell` or `RefCell` if it needs to be changed.
+pub struct Mutex<T> {
+    inner: UnsafeCell<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(value),
+        }
+    }
+
+    /// Borrows the data for the duration of the critical section.
+    pub fn borrow<'cs>(&'cs self, _cs: &'cs CriticalSection) -> &'cs T {
+        // SAFETY: The data can only be reached while interrupts are disabled, and there's
+        // only the one core, so nothing else can be accessing it.
+        unsafe { &*self.inner.get() }
+    }
+}
+
+// SAFETY: Access is only given inside a critical section. See above.
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+/// Catches any interrupt that fires without a handler being registered.
+///
+/// The startup code jumps here for every vector without a handler. By default it jumps
+/// to the reset vector, which would quietly restart the game instead of telling us
+/// something's wrong.
+#[cfg(not(test))]
+#[no_mangle]
+pub unsafe extern "avr-interrupt" fn __vector_default() {
+    crate::hal::blink_error_code(crate::hal::Error::UnexpectedInterrupt);
+}
+
+/// Used by the generated `interrupt` macro to declare the handler with the vector's symbol name.
+///
+/// When testing, the vector is declared as a normal function instead, so the handler is still
+/// used. The vector numbers differ between devices, so tests simulate the interrupt firing by
+/// calling the handler itself.
+#[macro_export]
+macro_rules! isr {
+    ($symbol:ident, $handler:path) => {
+        #[cfg(not(test))]
+        #[no_mangle]
+        pub unsafe extern "avr-interrupt" fn $symbol() {
+            $handler();
+        }
+
+        #[cfg(test)]
+        pub unsafe fn $symbol() {
+            $handler();
+        }
+    };
+}
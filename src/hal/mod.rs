@@ -16,36 +16,138 @@
 // License along with this library; if not, write to the Free Software
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
 
+// Defined before the modules so they can use it.
+
+/// Fails to compile if the condition is false.
+///
+/// There's no way of giving a message, so the error points at the failed check instead, which
+/// should be commented with what went wrong.
+macro_rules! const_assert {
+    ($cond:expr) => {
+        const _: [(); 0 - !{
+            const ASSERT: bool = $cond;
+            ASSERT
+        } as usize] = [];
+    };
+}
+
 #[macro_use]
 pub mod register;
+#[macro_use]
+pub mod device;
+#[macro_use]
+pub mod interrupt;
 pub mod clock;
+pub mod eeprom;
+pub mod error;
+mod error_codes;
 pub mod ports;
+#[macro_use]
 pub mod progmem;
+pub mod ram;
+#[cfg(test)]
+pub mod sim;
 pub mod twi;
 pub mod usart;
 
-const CPU_FREQ: u32 = 16_000_000;
+pub use error::Error;
 
-pub fn enable_interrupts() {
-    unsafe {
-        llvm_asm! {
-            "sei"
-            :
-            :
-            :
-            : "volatile"
-        }
-    }
+use core::cell::Cell;
+use interrupt::Mutex;
+
+#[cfg(any(
+    all(feature = "cpu-20mhz", feature = "cpu-8mhz"),
+    all(feature = "cpu-20mhz", feature = "cpu-1mhz"),
+    all(feature = "cpu-8mhz", feature = "cpu-1mhz"),
+))]
+compile_error!("More than one CPU frequency selected.");
+
+/// The CPU frequency, in Hz. This is 16MHz unless one of the `cpu-*mhz` features is enabled.
+///
+/// Everything timing-related is derived from this, and checked at compile time with
+/// `const_assert`, so a frequency the hardware can't be configured for fails to build.
+const CPU_FREQ: u32 = if cfg!(feature = "cpu-20mhz") {
+    20_000_000
+} else if cfg!(feature = "cpu-8mhz") {
+    8_000_000
+} else if cfg!(feature = "cpu-1mhz") {
+    1_000_000
+} else {
+    16_000_000
+};
+
+/// Tracks whether the peripherals have been taken.
+static TAKEN: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+/// Ownership tokens for each of the hardware blocks.
+///
+/// The drivers consume the token for their block when they're initialised, so only one
+/// driver can ever be using a block.
+pub struct Peripherals {
+    pub tc0: clock::TC0,
+    pub twi: twi::TWI0,
+    pub usart0: usart::USART0,
+    pub eeprom: eeprom::EEPROM,
+    #[cfg(feature = "atmega2560")]
+    pub porta: ports::PortA,
+    pub portb: ports::PortB,
+    pub portc: ports::PortC,
+    pub portd: ports::PortD,
+    #[cfg(any(feature = "atmega32u4", feature = "atmega2560"))]
+    pub porte: ports::PortE,
+    #[cfg(any(feature = "atmega32u4", feature = "atmega2560"))]
+    pub portf: ports::PortF,
+    #[cfg(feature = "atmega2560")]
+    pub portg: ports::PortG,
+    #[cfg(feature = "atmega2560")]
+    pub porth: ports::PortH,
+    #[cfg(feature = "atmega2560")]
+    pub portj: ports::PortJ,
+    #[cfg(feature = "atmega2560")]
+    pub portk: ports::PortK,
+    #[cfg(feature = "atmega2560")]
+    pub portl: ports::PortL,
 }
 
-pub fn disable_interrupts() {
-    unsafe {
-        llvm_asm! {
-            "cli"
-            :
-            :
-            :
-            : "volatile"
+impl Peripherals {
+    /// Returns the peripherals the first time it's called, and `None` after that.
+    pub fn take() -> Option<Peripherals> {
+        interrupt::free(|cs| {
+            if TAKEN.borrow(cs).replace(true) {
+                None
+            } else {
+                Some(unsafe { Peripherals::steal() })
+            }
+        })
+    }
+
+    /// SAFETY: Only one set of tokens may exist at a time, so this must only be used when
+    /// nothing else will use the peripherals again, such as when reporting a fatal error.
+    pub unsafe fn steal() -> Peripherals {
+        Peripherals {
+            tc0: clock::TC0::steal(),
+            twi: twi::TWI0::steal(),
+            usart0: usart::USART0::steal(),
+            eeprom: eeprom::EEPROM::steal(),
+            #[cfg(feature = "atmega2560")]
+            porta: ports::PortA::steal(),
+            portb: ports::PortB::steal(),
+            portc: ports::PortC::steal(),
+            portd: ports::PortD::steal(),
+            #[cfg(any(feature = "atmega32u4", feature = "atmega2560"))]
+            porte: ports::PortE::steal(),
+            #[cfg(any(feature = "atmega32u4", feature = "atmega2560"))]
+            portf: ports::PortF::steal(),
+            #[cfg(feature = "atmega2560")]
+            portg: ports::PortG::steal(),
+            #[cfg(feature = "atmega2560")]
+            porth: ports::PortH::steal(),
+            #[cfg(feature = "atmega2560")]
+            portj: ports::PortJ::steal(),
+            #[cfg(feature = "atmega2560")]
+            portk: ports::PortK::steal(),
+            #[cfg(feature = "atmega2560")]
+            portl: ports::PortL::steal(),
         }
     }
 }
@@ -58,7 +160,16 @@ pub fn disable_interrupts() {
 /// Close enough to blink an LED.
 #[inline(never)]
 pub fn delay_millis(mut ms: u16) {
-    const MAX_MS_PER_CALL: u16 = 16;
+    // The most that can be passed to `delay_micros` without its loop count overflowing.
+    const MAX_MS_PER_CALL: u16 = if CPU_FREQ >= 20_000_000 {
+        13
+    } else if CPU_FREQ >= 16_000_000 {
+        16
+    } else if CPU_FREQ >= 8_000_000 {
+        32
+    } else {
+        65
+    };
     const US_PER_MS: u16 = 1000;
 
     while ms > MAX_MS_PER_CALL {
@@ -74,29 +185,83 @@ pub fn delay_millis(mut ms: u16) {
 /// Its intended use is for the `delay_millis` function, so that it doesn't depend
 /// on a timer being configured.
 ///
-/// This was copied from the Arduino library's implementation, with the parts for
-/// other CPU frequencies ripped out.
+/// This was copied from the Arduino library's implementation, keeping the parts for the
+/// CPU frequencies we support. The branches are on a constant, so only one is compiled in.
 #[inline(never)]
 pub fn delay_micros(mut us: u16) {
-    // This function assumes a 16MHz clock.
     // Call = 4 cycles + 2 to 4 cycles to init `us` (2 for constant delay, 4 for variable)
 
-    // For a one-microsecond delay, simply return. The overhoad of the function call
-    // takes 14 (16) cycles, which is 1us.
+    if CPU_FREQ >= 20_000_000 {
+        // For a one-microsecond delay, simply return. The overhead of the function call
+        // takes 18 (20) cycles, which is 1us.
+        #[cfg(not(test))]
+        unsafe {
+            // Just waiting 4 cycles.
+            llvm_asm! {
+                "nop
+                nop
+                nop
+                nop"
+                :::: "volatile"
+            }
+        }
 
-    if us <= 1 {
-        return; // = 3 cycles ( 4 when true )
-    }
+        if us <= 1 {
+            return; // = 3 cycles ( 4 when true )
+        }
+
+        // The following loop takes 1/5 of a microsecond (4 cycles) per iteration, so
+        // execute it five times for each microsecond of delay requested.
+        us = (us << 2) + us; // = 7 cycles
+
+        // Account for the time taken in the preceeding commands. We just burned 26 (28)
+        // cycles above, remove 7, (7*4 = 28)us is at least 10 so we can subtract 7.
+        us -= 7;
+    } else if CPU_FREQ >= 16_000_000 {
+        // For a one-microsecond delay, simply return. The overhoad of the function call
+        // takes 14 (16) cycles, which is 1us.
+        if us <= 1 {
+            return; // = 3 cycles ( 4 when true )
+        }
+
+        // The following loop takes 1/4 of a microsecond (4 cycles) per iteration, so
+        // execute it four times for each microsecond of delay requested.
+        us <<= 2;
+
+        // Account for the time taken in the preceeding commands. We just burned 19 (21)
+        // cycles above, remove 5, (5*4 = 20)us is at least 8 so we can subtract 5.
+        us -= 5;
+    } else if CPU_FREQ >= 8_000_000 {
+        // For a one- and two-microsecond delay, simply return. The overhead of the function
+        // call takes 14 (16) cycles, which is 2us.
+        if us <= 2 {
+            return; // = 3 cycles ( 4 when true )
+        }
+
+        // The following loop takes 1/2 of a microsecond (4 cycles) per iteration, so
+        // execute it twice for each microsecond of delay requested.
+        us <<= 1; // = 2 cycles
+
+        // Account for the time taken in the preceeding commands. We just burned 17 (19)
+        // cycles above, remove 4, (4*4 = 16)us is at least 6 so we can subtract 4.
+        us -= 4;
+    } else {
+        // The 1MHz internal oscillator. The overhead of the function call is 14 (16) cycles,
+        // and we need at least 25us to subtract the 22 below.
+        if us <= 25 {
+            return; // = 3 cycles ( 4 when true )
+        }
 
-    // The following loop takes 1/4 of a microsecond (4 cycles) per iteration, so
-    // execute it four times for each microsecond of delay requested.
-    us <<= 2;
+        // Compensate for the time taken by the preceeding and next commands (about 22 cycles).
+        us -= 22;
 
-    // Account for the time taken in the preceeding commands. We just burned 19 (21)
-    // cycles above, remove 5, (5*4 = 20)us is at least 8 so we can subtract 5.
-    us -= 5;
+        // The following loop takes 4 microseconds (4 cycles) per iteration, so execute it
+        // us/4 times. `us` is at least 4, so this can't give a zero delay.
+        us >>= 2; // = 4 cycles
+    }
 
     // Busy wait.
+    #[cfg(not(test))]
     unsafe {
         llvm_asm! {
             "1: sbiw $0,1
@@ -113,15 +278,97 @@ pub fn delay_micros(mut us: u16) {
     // Return = 4 cycles.
 }
 
-/// Blinks an error code at the user.
+/// Implements the embedded-hal delay traits using `delay_millis` and `delay_micros`, for
+/// drivers that need to wait.
+#[cfg(feature = "embedded-hal-traits")]
+pub struct Delay;
+
+#[cfg(feature = "embedded-hal-traits")]
+mod delay_impls {
+    use super::{delay_micros, delay_millis, Delay};
+    use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+    impl DelayMs<u8> for Delay {
+        fn delay_ms(&mut self, ms: u8) {
+            delay_millis(ms as u16);
+        }
+    }
+
+    impl DelayMs<u16> for Delay {
+        fn delay_ms(&mut self, ms: u16) {
+            delay_millis(ms);
+        }
+    }
+
+    impl DelayMs<u32> for Delay {
+        fn delay_ms(&mut self, mut ms: u32) {
+            while ms > u16::MAX as u32 {
+                delay_millis(u16::MAX);
+                ms -= u16::MAX as u32;
+            }
+            delay_millis(ms as u16);
+        }
+    }
+
+    impl DelayUs<u8> for Delay {
+        fn delay_us(&mut self, us: u8) {
+            delay_micros(us as u16);
+        }
+    }
+
+    impl DelayUs<u16> for Delay {
+        fn delay_us(&mut self, mut us: u16) {
+            // Larger values would overflow `delay_micros`'s loop count at the higher
+            // frequencies.
+            const MAX_US_PER_CALL: u16 = 1000;
+
+            while us > MAX_US_PER_CALL {
+                delay_micros(MAX_US_PER_CALL);
+                us -= MAX_US_PER_CALL;
+            }
+            delay_micros(us);
+        }
+    }
+}
+
+/// Formats a number in decimal into the end of `buf`, returning the digits. Used instead of
+/// `core::fmt`, which takes up a lot of flash.
+pub fn format_decimal(number: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut number = number;
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = (number % 10) as u8 + b'0';
+        number /= 10;
+        if number == 0 {
+            break;
+        }
+    }
+
+    &buf[start..]
+}
+
+/// Blinks an error's code at the user.
 ///
-/// The code is the first 6 bits of the input number, with a short blink for 0,
-/// long blink for 1, and a 1 second delay between sequences.
+/// The code is the first 6 bits of `Error::code`, with a short blink for 0,
+/// long blink for 1, and a 1 second delay between sequences. The codes are listed in
+/// `error_codes.md`.
 ///
-/// It uses pin PB5, which my Arduino Nano has an onboard LED connected to.
-pub fn blink_error_code(code: u8) -> ! {
-    use ports::{PinMode, Port, PortB};
-    PortB::set_pin_mode(PortB::PB5, PinMode::Output);
+/// It uses the board's onboard LED, which is PB5 on my Arduino Nano.
+pub fn blink_error_code(error: Error) -> ! {
+    use crate::board::{Led, LED_ACTIVE_HIGH};
+
+    let code = error.code();
+
+    // SAFETY: We never return from here, so it doesn't matter if something else had the LED pin.
+    let mut led = unsafe { ports::Pin::<Led, _>::steal() }.into_output();
+    let mut set_led = |on: bool| {
+        if on == LED_ACTIVE_HIGH {
+            led.set_high()
+        } else {
+            led.set_low()
+        }
+    };
 
     loop {
         let mut code = code;
@@ -130,9 +377,9 @@ pub fn blink_error_code(code: u8) -> ! {
             let bit = code & 0x1;
             let blink_len = if bit == 0 { 100 } else { 250 };
 
-            PortB::set_port_high(PortB::PB5);
+            set_led(true);
             delay_millis(blink_len);
-            PortB::set_port_low(PortB::PB5);
+            set_led(false);
             delay_millis(200);
 
             code >>= 1;
@@ -140,3 +387,16 @@ pub fn blink_error_code(code: u8) -> ! {
         delay_millis(1000);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_formatted_without_leading_zeros() {
+        let mut buf = [0; 10];
+        assert_eq!(format_decimal(0, &mut buf), b"0");
+        assert_eq!(format_decimal(1050, &mut buf), b"1050");
+        assert_eq!(format_decimal(u32::MAX, &mut buf), b"4294967295");
+    }
+}
@@ -3,397 +3,719 @@
 //! Allows the user to handle the three registers on each port as a single unit,
 //! as well as providing more meaningful names to the operations.
 //!
-//! Each port structure has associated constants for each of the pins on that pin
-//! for ease of use.
+//! Each port is split into owned handles for each of its pins, with the pin's mode
+//! tracked in its type.
 
 #![allow(dead_code)]
 use crate::hal::register::Register;
 
 pub mod registers {
-    reg! {
-        /// Port B Data Register
-        PORTB: u8 {
-            addr: 0x25,
-            write mask: 0xFF,
-            bits: {
-                PORTB0 = 0, RW;
-                PORTB1 = 1, RW;
-                PORTB2 = 2, RW;
-                PORTB3 = 3, RW;
-                PORTB4 = 4, RW;
-                PORTB5 = 5, RW;
-                PORTB6 = 6, RW;
-                PORTB7 = 7, RW;
-            }
+    pub use crate::hal::device::{portb::*, portc::*, portd::*};
+
+    #[cfg(feature = "atmega2560")]
+    pub use crate::hal::device::{porta::*, portg::*, porth::*, portj::*, portk::*, portl::*};
+    #[cfg(any(feature = "atmega32u4", feature = "atmega2560"))]
+    pub use crate::hal::device::{porte::*, portf::*};
+}
+use registers::*;
+
+use core::{marker::PhantomData, ops::BitOr};
+
+// Pin modes are tracked in the type system, so that a pin configured as an output can't be
+// read as an input, and a button can't accidentally be handed a pin that's driving the line.
+
+/// Type-state for a pin configured as an input. The `PULL` parameter tracks whether the internal
+/// pullup resistor is enabled.
+pub struct Input<PULL>(PhantomData<PULL>);
+
+/// Type-state for an input with the internal pullup disabled. This is the state all pins are
+/// in after a reset.
+pub struct Floating;
+
+/// Type-state for an input with the internal pullup enabled.
+pub struct PullUp;
+
+/// Type-state for a pin configured as an output.
+pub struct Output;
+
+// Unfortunately, the utility functions on the Register trait expect the bits defined
+// on for the register, and won't work for the bit index stored on the pin.
+// We need to re-implement the bit-twiddling of a single bit.
+unsafe fn set_reg_bit<R: Register<DataType = u8>>(bit: u8) {
+    let mut val = R::get_value();
+    val |= 1 << bit;
+    R::set_raw_value(val);
+}
+
+unsafe fn clear_reg_bit<R: Register<DataType = u8>>(bit: u8) {
+    let mut val = R::get_value();
+    val &= !(1 << bit);
+    R::set_raw_value(val);
+}
+
+unsafe fn get_reg_bit<R: Register<DataType = u8>>(bit: u8) -> bool {
+    (R::get_value() & (1 << bit)) != 0
+}
+
+/// Shared behaviour on the ports of the device.
+///
+/// Associates the port with its three registers. Writes to the registers only happen
+/// through the pins split from the port, or through masks built from those pins.
+pub trait Port: Sized {
+    type PORT: Register<DataType = u8>;
+    type DDR: Register<DataType = u8>;
+    type PIN: Register<DataType = u8>;
+
+    /// Reads the PIN register once, returning the state of every pin on the port at the
+    /// same instant.
+    fn read_all() -> PinSet<Self> {
+        PinSet {
+            bits: unsafe { Self::PIN::get_value() },
+            _p: PhantomData,
         }
     }
-    reg! {
-        /// Port B Data Direction Register
-        DDRB: u8 {
-            addr: 0x24,
-            write mask: 0xFF,
-            bits: {
-                DDB0 = 0, RW;
-                DDB1 = 1, RW;
-                DDB2 = 2, RW;
-                DDB3 = 3, RW;
-                DDB4 = 4, RW;
-                DDB5 = 5, RW;
-                DDB6 = 6, RW;
-                DDB7 = 7, RW;
-            }
+
+    /// Drives the pins in `mask` high if they're also in `high`, and low otherwise.
+    ///
+    /// Rather than a read-modify-write of the PORT register, which could clobber changes
+    /// made by an interrupt to other pins, this works out which pins need to change and
+    /// writes them to the PIN register in one go. Pins not in `mask` are never written.
+    fn write_pins(mask: PinMask<'_, Self>, high: PinMask<'_, Self>) {
+        unsafe {
+            let toggle = (Self::PORT::get_value() ^ high.bits) & mask.bits;
+            Self::PIN::set_raw_value(toggle);
         }
     }
-    reg! {
-        /// Port B Input Pins Register
-        PINB: u8 {
-            addr: 0x23,
-            write mask: 0xFF,
-            bits: {
-                PINB0 = 0, RW;
-                PINB1 = 1, RW;
-                PINB2 = 2, RW;
-                PINB3 = 3, RW;
-                PINB4 = 4, RW;
-                PINB5 = 5, RW;
-                PINB6 = 6, RW;
-                PINB7 = 7, RW;
-            }
+
+    /// Toggles all the pins in `mask` with a single write to the PIN register.
+    fn toggle_pins(mask: PinMask<'_, Self>) {
+        unsafe {
+            Self::PIN::set_raw_value(mask.bits);
         }
     }
+}
 
-    reg! {
-        /// Port C Data Register
-        PORTC: u8 {
-            addr: 0x28,
-            write mask: 0x7F,
-            bits: {
-                PORTC0 = 0, RW;
-                PORTC1 = 1, RW;
-                PORTC2 = 2, RW;
-                PORTC3 = 3, RW;
-                PORTC4 = 4, RW;
-                PORTC5 = 5, RW;
-                PORTC6 = 6, RW;
-            }
-        }
+/// A snapshot of the state of every pin on a port, taken from a single read.
+///
+/// Only pins configured as inputs can be looked up in the snapshot.
+pub struct PinSet<P: Port> {
+    bits: u8,
+    _p: PhantomData<P>,
+}
+
+// Deriving these would require the port to be Copy, which it mustn't be.
+impl<P: Port> Clone for PinSet<P> {
+    fn clone(&self) -> Self {
+        *self
     }
-    reg! {
-        /// Port C Data Direction Register
-        DDRC: u8 {
-            addr: 0x27,
-            write mask: 0x7F,
-            bits: {
-                DDRC0 = 0, RW;
-                DDRC1 = 1, RW;
-                DDRC2 = 2, RW;
-                DDRC3 = 3, RW;
-                DDRC4 = 4, RW;
-                DDRC5 = 5, RW;
-                DDRC6 = 6, RW;
-            }
+}
+impl<P: Port> Copy for PinSet<P> {}
+
+impl<P: Port> PinSet<P> {
+    /// Returns whether the given pin was high when the snapshot was taken.
+    pub fn is_high<ID: PinId<Port = P>, PULL>(&self, _pin: &Pin<ID, Input<PULL>>) -> bool {
+        (self.bits & (1 << ID::BIT)) != 0
+    }
+
+    /// Returns whether the given pin was low when the snapshot was taken.
+    pub fn is_low<ID: PinId<Port = P>, PULL>(&self, pin: &Pin<ID, Input<PULL>>) -> bool {
+        !self.is_high(pin)
+    }
+
+    pub fn raw_value(&self) -> u8 {
+        self.bits
+    }
+}
+
+/// A set of output pins on a single port, used to write to several pins at once.
+///
+/// Can only be built from output pins, so writing with a mask can't affect a pin that
+/// isn't owned by the caller. The mask borrows the pins it was built from, so they can't be
+/// changed to inputs while it's held. Masks are combined with `|`.
+pub struct PinMask<'a, P: Port> {
+    bits: u8,
+    _p: PhantomData<(P, &'a ())>,
+}
+
+impl<P: Port> Clone for PinMask<'_, P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<P: Port> Copy for PinMask<'_, P> {}
+
+impl<P: Port> PinMask<'_, P> {
+    /// A mask containing no pins.
+    pub const fn empty() -> Self {
+        Self {
+            bits: 0,
+            _p: PhantomData,
         }
     }
-    reg! {
-        /// Port C Input Pins Register
-        PINC: u8 {
-            addr: 0x26,
-            write mask: 0x7F,
-            bits: {
-                PINC0 = 0, RW;
-                PINC1 = 1, RW;
-                PINC2 = 2, RW;
-                PINC3 = 3, RW;
-                PINC4 = 4, RW;
-                PINC5 = 5, RW;
-                PINC6 = 6, RW;
-            }
+}
+
+impl<'a, P: Port> BitOr for PinMask<'a, P> {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self {
+            bits: self.bits | rhs.bits,
+            _p: PhantomData,
         }
     }
+}
 
-    reg! {
-        /// Port D Data Register
-        PORTD:  u8 {
-            addr: 0x2B,
-            write mask: 0xFF,
-            bits: {
-                PORTD0 = 0, RW;
-                PORTD1 = 1, RW;
-                PORTD2 = 2, RW;
-                PORTD3 = 3, RW;
-                PORTD4 = 4, RW;
-                PORTD5 = 5, RW;
-                PORTD6 = 6, RW;
-                PORTD7 = 7, RW;
-            }
+/// Identifies a single pin on a port.
+///
+/// This is only implemented on uninhabited types, so the pins only exist at the type level.
+/// The only way to get a handle to a pin is to split it from its port.
+pub trait PinId {
+    type Port: Port;
+    const BIT: u8;
+}
+
+/// An owned handle to a single pin, in the mode given by `MODE`.
+///
+/// Changing the mode consumes the handle and returns one in the new mode.
+pub struct Pin<ID: PinId, MODE> {
+    _p: PhantomData<(ID, MODE)>,
+}
+
+impl<ID: PinId, MODE> Pin<ID, MODE> {
+    const fn new() -> Self {
+        Self { _p: PhantomData }
+    }
+
+    /// Sets the DDR bit, configuring the pin as an output.
+    ///
+    /// Does not alter the PORT value, so a pin that had its pullup enabled will be driven high.
+    pub fn into_output(self) -> Pin<ID, Output> {
+        unsafe {
+            set_reg_bit::<<ID::Port as Port>::DDR>(ID::BIT);
         }
+        Pin::new()
     }
-    reg! {
-        /// Port D Data Direction Register
-        DDRD:   u8 {
-            addr: 0x2A,
-            write mask: 0xFF,
-            bits: {
-                DDD0 = 0, RW;
-                DDD1 = 1, RW;
-                DDD2 = 2, RW;
-                DDD3 = 3, RW;
-                DDD4 = 4, RW;
-                DDD5 = 5, RW;
-                DDD6 = 6, RW;
-                DDD7 = 7, RW;
-            }
+
+    /// Clears the DDR bit and PORT bits, configuring the pin as an input with no pullup.
+    pub fn into_floating_input(self) -> Pin<ID, Input<Floating>> {
+        unsafe {
+            clear_reg_bit::<<ID::Port as Port>::DDR>(ID::BIT);
+            clear_reg_bit::<<ID::Port as Port>::PORT>(ID::BIT);
         }
+        Pin::new()
     }
-    reg! {
-        /// Port D Input Pins Register
-        PIND:   u8 {
-            addr: 0x29,
-            write mask: 0xFF,
-            bits: {
-                PIND0 = 0, RW;
-                PIND1 = 1, RW;
-                PIND2 = 2, RW;
-                PIND3 = 3, RW;
-                PIND4 = 4, RW;
-                PIND5 = 5, RW;
-                PIND6 = 6, RW;
-                PIND7 = 7, RW;
-            }
+
+    /// Clears the DDR bit and sets the PORT bit, configuring the pin as an input with the
+    /// internal pullup enabled.
+    pub fn into_pull_up_input(self) -> Pin<ID, Input<PullUp>> {
+        unsafe {
+            clear_reg_bit::<<ID::Port as Port>::DDR>(ID::BIT);
+            set_reg_bit::<<ID::Port as Port>::PORT>(ID::BIT);
         }
+        Pin::new()
     }
 }
-use registers::*;
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub enum PinMode {
-    Output,
-    Input,
-    InputPullup,
-}
-
-// Unfortunately, the bit abstraction doesn't really work well here, because I want
-// to represent each port as a single module, instead of three separate registers. The
-// abstraction for the registers requires that each bit be associated with its parent
-// register, meaning I can't do that.
-
-// Because the bits on each of the three registers have exactly the same meaning, and
-// are all read-write, I'm representing them with an enum instead.
-pub trait PortBit {
-    fn bit(&self) -> u8;
-}
-
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub enum PortBPins {
-    PB0,
-    PB1,
-    PB2,
-    PB3,
-    PB4,
-    PB5,
-    PB6,
-    PB7,
-}
-
-impl PortBit for PortBPins {
-    fn bit(&self) -> u8 {
-        use PortBPins::*;
-        match self {
-            PB0 => 0,
-            PB1 => 1,
-            PB2 => 2,
-            PB3 => 3,
-            PB4 => 4,
-            PB5 => 5,
-            PB6 => 6,
-            PB7 => 7,
-        }
+impl<ID: PinId> Pin<ID, Input<Floating>> {
+    /// Creates a handle to a single pin without splitting its port, for use when reporting a
+    /// fatal error. The pin's mode isn't changed, so it should be converted straight away.
+    ///
+    /// SAFETY: Nothing else may be using the pin.
+    pub unsafe fn steal() -> Self {
+        Pin::new()
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub enum PortCPins {
-    PC0,
-    PC1,
-    PC2,
-    PC3,
-    PC4,
-    PC5,
-    PC6,
-}
-
-impl PortBit for PortCPins {
-    fn bit(&self) -> u8 {
-        use PortCPins::*;
-        match self {
-            PC0 => 0,
-            PC1 => 1,
-            PC2 => 2,
-            PC3 => 3,
-            PC4 => 4,
-            PC5 => 5,
-            PC6 => 6,
+impl<ID: PinId> Pin<ID, Output> {
+    /// Returns a mask containing only this pin, for use with `Port::write_pins` and
+    /// `Port::toggle_pins`.
+    pub fn mask(&self) -> PinMask<'_, ID::Port> {
+        PinMask {
+            bits: 1 << ID::BIT,
+            _p: PhantomData,
         }
     }
+
+    /// Sets the appropriate bit in the PORT register, driving the pin high.
+    pub fn set_high(&mut self) {
+        ID::Port::write_pins(self.mask(), self.mask());
+    }
+
+    /// Clears the appropriate bit in the PORT register, pulling the pin low.
+    pub fn set_low(&mut self) {
+        ID::Port::write_pins(self.mask(), PinMask::empty());
+    }
+
+    /// Writes the pin's bit to the PIN register, toggling the current state of the PORT register.
+    ///
+    /// Only the pin's bit is written, as writing a 1 to any other bit would toggle that pin too.
+    pub fn toggle(&mut self) {
+        ID::Port::toggle_pins(self.mask());
+    }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub enum PortDPins {
-    PD0,
-    PD1,
-    PD2,
-    PD3,
-    PD4,
-    PD5,
-    PD6,
-    PD7,
-}
-
-impl PortBit for PortDPins {
-    fn bit(&self) -> u8 {
-        use PortDPins::*;
-        match self {
-            PD0 => 0,
-            PD1 => 1,
-            PD2 => 2,
-            PD3 => 3,
-            PD4 => 4,
-            PD5 => 5,
-            PD6 => 6,
-            PD7 => 7,
-        }
+impl<ID: PinId, PULL> Pin<ID, Input<PULL>> {
+    /// Reads from the PIN register, returning whether the pin is high.
+    pub fn is_high(&self) -> bool {
+        unsafe { get_reg_bit::<<ID::Port as Port>::PIN>(ID::BIT) }
+    }
+
+    /// Reads from the PIN register, returning whether the pin is low.
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
     }
 }
 
-// Unfortunately, the utility functions on the Register trait expect the bits defined
-// on for the register, and won't work for the shared bits above.
-// We need to re-implement the bit-twiddling of a single bit.
-unsafe fn set_reg_bit<R: Register<DataType = u8>>(bit: u8) {
-    let mut val = R::get_value();
-    val |= 1 << bit;
-    R::set_raw_value(val);
+#[cfg(feature = "embedded-hal-traits")]
+impl<ID: PinId> embedded_hal::digital::v2::OutputPin for Pin<ID, Output> {
+    type Error = core::convert::Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Pin::set_low(self);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Pin::set_high(self);
+        Ok(())
+    }
 }
 
-unsafe fn clear_reg_bit<R: Register<DataType = u8>>(bit: u8) {
-    let mut val = R::get_value();
-    val &= !(1 << bit);
-    R::set_raw_value(val);
+#[cfg(feature = "embedded-hal-traits")]
+impl<ID: PinId> embedded_hal::digital::v2::StatefulOutputPin for Pin<ID, Output> {
+    /// Reads back from the PORT register, so this is what the pin is being driven to, not
+    /// what's on it.
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(unsafe { get_reg_bit::<<ID::Port as Port>::PORT>(ID::BIT) })
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        self.is_set_high().map(|high| !high)
+    }
 }
 
-unsafe fn get_reg_bit<R: Register<DataType = u8>>(bit: u8) -> bool {
-    (R::get_value() & (1 << bit)) != 0
+#[cfg(feature = "embedded-hal-traits")]
+impl<ID: PinId> embedded_hal::digital::v2::ToggleableOutputPin for Pin<ID, Output> {
+    type Error = core::convert::Infallible;
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        Pin::toggle(self);
+        Ok(())
+    }
 }
 
-/// Shared behaviour on the three ports available to the Atmega328P.
-pub trait Port {
-    type ValidPins: PortBit + Copy;
-    type PORT: Register<DataType = u8>;
-    type DDR: Register<DataType = u8>;
-    type PIN: Register<DataType = u8>;
+#[cfg(feature = "embedded-hal-traits")]
+impl<ID: PinId, PULL> embedded_hal::digital::v2::InputPin for Pin<ID, Input<PULL>> {
+    type Error = core::convert::Infallible;
 
-    /// Sets the DDR and PORT registers to the appropriate values for the given pin mode.
-    ///
-    /// Does not alter the PORT value when set to Output.
-    fn set_pin_mode(pin: Self::ValidPins, mode: PinMode) {
-        unsafe {
-            let bit = pin.bit();
-            match mode {
-                PinMode::Output => {
-                    set_reg_bit::<Self::DDR>(bit);
-                }
-                PinMode::Input => {
-                    set_reg_bit::<Self::DDR>(bit);
-                    clear_reg_bit::<Self::PORT>(bit);
-                }
-                PinMode::InputPullup => {
-                    set_reg_bit::<Self::DDR>(pin.bit());
-                    set_reg_bit::<Self::PORT>(bit);
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(Pin::is_high(self))
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(Pin::is_low(self))
+    }
+}
+
+/// Declaring a port, its pins, and the structure it splits into is very repetitive, so this
+/// macro does it for us.
+macro_rules! port {
+    (
+        $(#[$port_doc:meta])*
+        $port:ident => $pins:ident {
+            PORT: $port_reg:ident,
+            DDR: $ddr_reg:ident,
+            PIN: $pin_reg:ident,
+            pins: {
+                $( $pin:ident: $field:ident = $bit:expr, )+
+            }
+        }
+    ) => {
+        $(#[$port_doc])*
+        pub struct $port {
+            _p: (),
+        }
+
+        impl Port for $port {
+            type PORT = $port_reg;
+            type DDR = $ddr_reg;
+            type PIN = $pin_reg;
+        }
+
+        impl $port {
+            /// Creates a handle to the port. Normally this is obtained from `Peripherals::take`.
+            ///
+            /// SAFETY: Only one handle to the port may exist at a time, otherwise the pins
+            /// could be split out twice.
+            pub unsafe fn steal() -> Self {
+                Self { _p: () }
+            }
+
+            /// Splits the port into its individual pins. All pins start out as floating
+            /// inputs, as that's what the hardware resets to.
+            pub fn split(self) -> $pins {
+                $pins {
+                    $( $field: Pin::new(), )+
                 }
             }
         }
+
+        /// The individual pins split from the port.
+        pub struct $pins {
+            $( pub $field: Pin<$pin, Input<Floating>>, )+
+        }
+
+        $(
+            pub enum $pin {}
+            impl PinId for $pin {
+                type Port = $port;
+                const BIT: u8 = $bit;
+            }
+        )+
+    };
+}
+
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port A.
+    PortA => PortAPins {
+        PORT: PORTA,
+        DDR: DDRA,
+        PIN: PINA,
+        pins: {
+            PA0: pa0 = 0,
+            PA1: pa1 = 1,
+            PA2: pa2 = 2,
+            PA3: pa3 = 3,
+            PA4: pa4 = 4,
+            PA5: pa5 = 5,
+            PA6: pa6 = 6,
+            PA7: pa7 = 7,
+        }
     }
+}
 
-    /// Sets the appropriate bit in the PORT register to high. In Output mode, will drive the pin high;
-    /// in Input mode will enable the internal pullup resistor.
-    fn set_port_high(pin: Self::ValidPins) {
-        unsafe {
-            set_reg_bit::<Self::PORT>(pin.bit());
+port! {
+    /// Port B.
+    PortB => PortBPins {
+        PORT: PORTB,
+        DDR: DDRB,
+        PIN: PINB,
+        pins: {
+            PB0: pb0 = 0,
+            PB1: pb1 = 1,
+            PB2: pb2 = 2,
+            PB3: pb3 = 3,
+            PB4: pb4 = 4,
+            PB5: pb5 = 5,
+            PB6: pb6 = 6,
+            PB7: pb7 = 7,
+        }
+    }
+}
+
+#[cfg(any(feature = "atmega328p", feature = "atmega168"))]
+port! {
+    /// Port C. Only has 7 pins, and PC6 is usually the reset pin.
+    PortC => PortCPins {
+        PORT: PORTC,
+        DDR: DDRC,
+        PIN: PINC,
+        pins: {
+            PC0: pc0 = 0,
+            PC1: pc1 = 1,
+            PC2: pc2 = 2,
+            PC3: pc3 = 3,
+            PC4: pc4 = 4,
+            PC5: pc5 = 5,
+            PC6: pc6 = 6,
         }
     }
+}
 
-    /// Sets the appropriate bit in the PORT register to low. In Output mode, will pull the pin low;
-    /// in Input mode will disable the internal pullup resistor.
-    fn set_port_low(pin: Self::ValidPins) {
-        unsafe {
-            clear_reg_bit::<Self::PORT>(pin.bit());
+#[cfg(feature = "atmega32u4")]
+port! {
+    /// Port C. Only PC6 and PC7 are present on the 32U4.
+    PortC => PortCPins {
+        PORT: PORTC,
+        DDR: DDRC,
+        PIN: PINC,
+        pins: {
+            PC6: pc6 = 6,
+            PC7: pc7 = 7,
+        }
+    }
+}
+
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port C.
+    PortC => PortCPins {
+        PORT: PORTC,
+        DDR: DDRC,
+        PIN: PINC,
+        pins: {
+            PC0: pc0 = 0,
+            PC1: pc1 = 1,
+            PC2: pc2 = 2,
+            PC3: pc3 = 3,
+            PC4: pc4 = 4,
+            PC5: pc5 = 5,
+            PC6: pc6 = 6,
+            PC7: pc7 = 7,
         }
     }
+}
 
-    /// Sets the given bit in the PIN register, toggling the current state of the PORT register.
-    fn set_pin_toggle(pin: Self::ValidPins) {
-        unsafe {
-            set_reg_bit::<Self::PIN>(pin.bit());
+port! {
+    /// Port D.
+    PortD => PortDPins {
+        PORT: PORTD,
+        DDR: DDRD,
+        PIN: PIND,
+        pins: {
+            PD0: pd0 = 0,
+            PD1: pd1 = 1,
+            PD2: pd2 = 2,
+            PD3: pd3 = 3,
+            PD4: pd4 = 4,
+            PD5: pd5 = 5,
+            PD6: pd6 = 6,
+            PD7: pd7 = 7,
+        }
+    }
+}
+
+#[cfg(feature = "atmega32u4")]
+port! {
+    /// Port E. Only PE2 and PE6 are present on the 32U4.
+    PortE => PortEPins {
+        PORT: PORTE,
+        DDR: DDRE,
+        PIN: PINE,
+        pins: {
+            PE2: pe2 = 2,
+            PE6: pe6 = 6,
+        }
+    }
+}
+
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port E.
+    PortE => PortEPins {
+        PORT: PORTE,
+        DDR: DDRE,
+        PIN: PINE,
+        pins: {
+            PE0: pe0 = 0,
+            PE1: pe1 = 1,
+            PE2: pe2 = 2,
+            PE3: pe3 = 3,
+            PE4: pe4 = 4,
+            PE5: pe5 = 5,
+            PE6: pe6 = 6,
+            PE7: pe7 = 7,
+        }
+    }
+}
+
+#[cfg(feature = "atmega32u4")]
+port! {
+    /// Port F. PF2 and PF3 aren't present on the 32U4.
+    PortF => PortFPins {
+        PORT: PORTF,
+        DDR: DDRF,
+        PIN: PINF,
+        pins: {
+            PF0: pf0 = 0,
+            PF1: pf1 = 1,
+            PF4: pf4 = 4,
+            PF5: pf5 = 5,
+            PF6: pf6 = 6,
+            PF7: pf7 = 7,
         }
     }
+}
 
-    /// Reads from the PIN register, returning the current state of the pin.
-    fn get_pin_state(pin: Self::ValidPins) -> bool {
-        unsafe { get_reg_bit::<Self::PIN>(pin.bit()) }
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port F.
+    PortF => PortFPins {
+        PORT: PORTF,
+        DDR: DDRF,
+        PIN: PINF,
+        pins: {
+            PF0: pf0 = 0,
+            PF1: pf1 = 1,
+            PF2: pf2 = 2,
+            PF3: pf3 = 3,
+            PF4: pf4 = 4,
+            PF5: pf5 = 5,
+            PF6: pf6 = 6,
+            PF7: pf7 = 7,
+        }
     }
 }
 
-pub struct PortB;
-impl Port for PortB {
-    type PORT = PORTB;
-    type PIN = PINB;
-    type DDR = DDRB;
-    type ValidPins = PortBPins;
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port G. Only has 6 pins.
+    PortG => PortGPins {
+        PORT: PORTG,
+        DDR: DDRG,
+        PIN: PING,
+        pins: {
+            PG0: pg0 = 0,
+            PG1: pg1 = 1,
+            PG2: pg2 = 2,
+            PG3: pg3 = 3,
+            PG4: pg4 = 4,
+            PG5: pg5 = 5,
+        }
+    }
 }
 
-impl PortB {
-    pub const PB0: PortBPins = PortBPins::PB0;
-    pub const PB1: PortBPins = PortBPins::PB1;
-    pub const PB2: PortBPins = PortBPins::PB2;
-    pub const PB3: PortBPins = PortBPins::PB3;
-    pub const PB4: PortBPins = PortBPins::PB4;
-    pub const PB5: PortBPins = PortBPins::PB5;
-    pub const PB6: PortBPins = PortBPins::PB6;
-    pub const PB7: PortBPins = PortBPins::PB7;
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port H.
+    PortH => PortHPins {
+        PORT: PORTH,
+        DDR: DDRH,
+        PIN: PINH,
+        pins: {
+            PH0: ph0 = 0,
+            PH1: ph1 = 1,
+            PH2: ph2 = 2,
+            PH3: ph3 = 3,
+            PH4: ph4 = 4,
+            PH5: ph5 = 5,
+            PH6: ph6 = 6,
+            PH7: ph7 = 7,
+        }
+    }
 }
 
-pub struct PortC;
-impl Port for PortC {
-    type PORT = PORTC;
-    type PIN = PINC;
-    type DDR = DDRC;
-    type ValidPins = PortCPins;
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port J.
+    PortJ => PortJPins {
+        PORT: PORTJ,
+        DDR: DDRJ,
+        PIN: PINJ,
+        pins: {
+            PJ0: pj0 = 0,
+            PJ1: pj1 = 1,
+            PJ2: pj2 = 2,
+            PJ3: pj3 = 3,
+            PJ4: pj4 = 4,
+            PJ5: pj5 = 5,
+            PJ6: pj6 = 6,
+            PJ7: pj7 = 7,
+        }
+    }
 }
 
-impl PortC {
-    pub const PC0: PortCPins = PortCPins::PC0;
-    pub const PC1: PortCPins = PortCPins::PC1;
-    pub const PC2: PortCPins = PortCPins::PC2;
-    pub const PC3: PortCPins = PortCPins::PC3;
-    pub const PC4: PortCPins = PortCPins::PC4;
-    pub const PC5: PortCPins = PortCPins::PC5;
-    pub const PC6: PortCPins = PortCPins::PC6;
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port K.
+    PortK => PortKPins {
+        PORT: PORTK,
+        DDR: DDRK,
+        PIN: PINK,
+        pins: {
+            PK0: pk0 = 0,
+            PK1: pk1 = 1,
+            PK2: pk2 = 2,
+            PK3: pk3 = 3,
+            PK4: pk4 = 4,
+            PK5: pk5 = 5,
+            PK6: pk6 = 6,
+            PK7: pk7 = 7,
+        }
+    }
 }
 
-pub struct PortD;
-impl Port for PortD {
-    type PORT = PORTD;
-    type PIN = PIND;
-    type DDR = DDRD;
-    type ValidPins = PortDPins;
+#[cfg(feature = "atmega2560")]
+port! {
+    /// Port L.
+    PortL => PortLPins {
+        PORT: PORTL,
+        DDR: DDRL,
+        PIN: PINL,
+        pins: {
+            PL0: pl0 = 0,
+            PL1: pl1 = 1,
+            PL2: pl2 = 2,
+            PL3: pl3 = 3,
+            PL4: pl4 = 4,
+            PL5: pl5 = 5,
+            PL6: pl6 = 6,
+            PL7: pl7 = 7,
+        }
+    }
 }
 
-impl PortD {
-    pub const PD0: PortDPins = PortDPins::PD0;
-    pub const PD1: PortDPins = PortDPins::PD1;
-    pub const PD2: PortDPins = PortDPins::PD2;
-    pub const PD3: PortDPins = PortDPins::PD3;
-    pub const PD4: PortDPins = PortDPins::PD4;
-    pub const PD5: PortDPins = PortDPins::PD5;
-    pub const PD6: PortDPins = PortDPins::PD6;
-    pub const PD7: PortDPins = PortDPins::PD7;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::sim;
+
+    #[test]
+    fn pin_modes_set_ddr_and_port() {
+        let _hw = sim::reset();
+        sim::set::<PORTD>(0b0001_0000);
+        let pins = unsafe { PortD::steal() }.split();
+
+        let _output = pins.pd2.into_output();
+        let _pull_up = pins.pd3.into_pull_up_input();
+        let _floating = pins.pd4.into_floating_input();
+
+        assert_eq!(sim::get::<DDRD>(), 0b0000_0100);
+        assert_eq!(sim::get::<PORTD>(), 0b0000_1000);
+    }
+
+    #[test]
+    fn output_writes_only_change_their_pin() {
+        let _hw = sim::reset();
+        sim::set::<PORTB>(0b1000_0001);
+        let mut led = unsafe { PortB::steal() }.split().pb5.into_output();
+
+        led.set_high();
+        assert_eq!(sim::get::<PORTB>(), 0b1010_0001);
+        led.set_high();
+        assert_eq!(sim::get::<PORTB>(), 0b1010_0001);
+        led.toggle();
+        assert_eq!(sim::get::<PORTB>(), 0b1000_0001);
+        led.toggle();
+        led.set_low();
+        assert_eq!(sim::get::<PORTB>(), 0b1000_0001);
+    }
+
+    #[test]
+    fn masks_write_several_pins_at_once() {
+        let _hw = sim::reset();
+        sim::set::<PORTB>(0b1000_0100);
+        let pins = unsafe { PortB::steal() }.split();
+        let pb0 = pins.pb0.into_output();
+        let pb2 = pins.pb2.into_output();
+
+        PortB::write_pins(pb0.mask() | pb2.mask(), pb0.mask());
+        assert_eq!(sim::get::<PORTB>(), 0b1000_0001);
+        PortB::toggle_pins(pb0.mask() | pb2.mask());
+        assert_eq!(sim::get::<PORTB>(), 0b1000_0100);
+    }
+
+    #[test]
+    fn snapshot_reads_input_pins() {
+        let _hw = sim::reset();
+        sim::set::<PIND>(0b0001_0000);
+        let pins = unsafe { PortD::steal() }.split();
+        let pd3 = pins.pd3.into_pull_up_input();
+        let pd4 = pins.pd4.into_pull_up_input();
+
+        let snapshot = PortD::read_all();
+        assert!(snapshot.is_low(&pd3));
+        assert!(snapshot.is_high(&pd4));
+    }
 }
@@ -14,10 +14,225 @@
 //!
 //! I cannot use the Index trait from corelib here, because Index requires the return type
 //! be a reference, not the data itself.
+//!
+//! Other data, such as structs and `u16` tables, can be declared in PROGMEM with the
+//! `progmem!` macro, which wraps them in a `PGMValue` or `PGMArray` that loads them with `LPM`.
+
+use core::{
+    mem::{self, MaybeUninit},
+    ops::{Bound, RangeBounds},
+};
+
+/// Declares statics stored in PROGMEM. Arrays become a `PGMArray`, and anything else becomes a
+/// `PGMValue`. The type must be `Copy`, as reading it gives a copy in RAM.
+///
+/// ```
+/// progmem! {
+///     /// The damage each weapon does.
+///     pub static DAMAGE: [u16; 3] = [4, 6, 10];
+///     static GREETING: [u8; 5] = *b"Hello";
+///     static START: Position = Position { x: 1, y: 1 };
+/// }
+///
+/// let damage = DAMAGE.load(1);
+/// let start = START.load();
+/// ```
+#[macro_export]
+macro_rules! progmem {
+    () => {};
+
+    (
+        $(#[$attr:meta])*
+        $vis:vis static $name:ident : [$ty:ty; $len:expr] = $value:expr;
+        $($rest:tt)*
+    ) => {
+        $(#[$attr])*
+        $vis static $name: crate::hal::progmem::PGMArray<$ty, $len> = {
+            #[link_section = ".text"]
+            static DATA: [$ty; $len] = $value;
+
+            // SAFETY: DATA was just put in PROGMEM.
+            unsafe { crate::hal::progmem::PGMArray::from_raw(&DATA) }
+        };
+
+        progmem! { $($rest)* }
+    };
+
+    (
+        $(#[$attr:meta])*
+        $vis:vis static $name:ident : $ty:ty = $value:expr;
+        $($rest:tt)*
+    ) => {
+        $(#[$attr])*
+        $vis static $name: crate::hal::progmem::PGMValue<$ty> = {
+            #[link_section = ".text"]
+            static DATA: $ty = $value;
+
+            // SAFETY: DATA was just put in PROGMEM.
+            unsafe { crate::hal::progmem::PGMValue::from_raw(&DATA) }
+        };
+
+        progmem! { $($rest)* }
+    };
+}
+
+/// Reads a single byte from PROGMEM.
+///
+/// SAFETY: The pointer MUST be pointing at PROGMEM, not RAM.
+#[inline(always)]
+unsafe fn read_byte(addr: *const u8) -> u8 {
+    // The host doesn't have separate program memory, so the data is just in RAM.
+    #[cfg(test)]
+    return addr.read();
+
+    #[cfg(not(test))]
+    {
+        let addr = addr as usize;
+        let out;
+
+        llvm_asm! {
+            "lpm $0, Z"
+            : "=r"(out)
+            : "{r31}"(addr >> 8),"{r30}"(addr)
+            : "r31", "r30"
+            : "volatile"
+        }
+
+        out
+    }
+}
+
+/// Copies a run of bytes from PROGMEM into RAM.
+///
+/// Reading a byte at a time reloads Z for every byte, so this uses the post-increment forms
+/// of `LPM` and `ST` instead, for 9 cycles per byte.
+///
+/// SAFETY: `src` MUST be pointing at PROGMEM, not RAM, and both must be valid for `len` bytes.
+unsafe fn copy_bytes(src: *const u8, dst: *mut u8, len: usize) {
+    // The loop can't copy nothing, the same as the ones in `intrinsics`.
+    if len == 0 {
+        return;
+    }
+
+    #[cfg(test)]
+    dst.copy_from_nonoverlapping(src, len);
+
+    #[cfg(not(test))]
+    {
+        let src = src as usize;
+        let dst = dst as usize;
+
+        llvm_asm! {
+            "1: lpm r0, Z+
+            st X+, r0
+            sbiw r24, 1
+            brne 1b"
+            :
+            : "{r31}"(src >> 8),"{r30}"(src),"{r27}"(dst >> 8),"{r26}"(dst),"{r25}"(len >> 8),"{r24}"(len)
+            : "r0", "r24", "r25", "r26", "r27", "r30", "r31", "memory"
+            : "volatile"
+        }
+    }
+}
+
+/// Reads a value from PROGMEM.
+///
+/// SAFETY: The pointer MUST be pointing at PROGMEM, not RAM, and at a valid `T`.
+unsafe fn read_value<T: Copy>(addr: *const T) -> T {
+    let mut out = MaybeUninit::<T>::uninit();
+    copy_bytes(
+        addr as *const u8,
+        out.as_mut_ptr() as *mut u8,
+        mem::size_of::<T>(),
+    );
+
+    out.assume_init()
+}
+
+/// A single value stored in PROGMEM, declared with the `progmem!` macro.
+pub struct PGMValue<T> {
+    addr: *const T,
+}
+
+impl<T> PGMValue<T> {
+    /// SAFETY: The input pointer MUST be pointing at PROGMEM, not RAM.
+    pub const unsafe fn from_raw(addr: *const T) -> Self {
+        Self { addr }
+    }
+}
+
+impl<T: Copy> PGMValue<T> {
+    /// Copies the value out of PROGMEM.
+    pub fn load(&self) -> T {
+        unsafe { read_value(self.addr) }
+    }
+}
+
+// SAFETY: PROGMEM can't be written to while the program's running.
+unsafe impl<T> Sync for PGMValue<T> {}
+
+/// An array stored in PROGMEM, declared with the `progmem!` macro.
+///
+/// Elements are copied out one at a time, so large tables don't need to fit in RAM.
+pub struct PGMArray<T, const N: usize> {
+    addr: *const [T; N],
+}
+
+impl<T, const N: usize> PGMArray<T, N> {
+    /// SAFETY: The input pointer MUST be pointing at PROGMEM, not RAM.
+    pub const unsafe fn from_raw(addr: *const [T; N]) -> Self {
+        Self { addr }
+    }
+
+    pub const fn len(&self) -> usize {
+        N
+    }
+}
+
+impl<T: Copy, const N: usize> PGMArray<T, N> {
+    /// Copies a single element out of PROGMEM.
+    pub fn load(&self, idx: usize) -> T {
+        if idx >= N {
+            panic!("Index out of bounds: {}", idx);
+        }
+
+        unsafe { read_value((self.addr as *const T).add(idx)) }
+    }
+}
+
+impl<const N: usize> PGMArray<u8, N> {
+    /// Byte arrays, such as strings and image data, can be used anywhere a `PGMSlice` can.
+    pub fn as_slice(&self) -> PGMSlice {
+        PGMSlice {
+            addr: self.addr as *const u8,
+            len: N,
+        }
+    }
+}
+
+// SAFETY: See PGMValue.
+unsafe impl<T, const N: usize> Sync for PGMArray<T, N> {}
 
 pub trait ByteBundle {
     fn get(&self, idx: usize) -> u8;
     fn length(&self) -> usize;
+
+    /// Copies all of the bytes into `dst`, which must be the same length.
+    fn copy_to(&self, dst: &mut [u8]) {
+        assert_eq!(self.length(), dst.len(), "Mismatched lengths");
+
+        for (i, byte) in dst.iter_mut().enumerate() {
+            *byte = self.get(i);
+        }
+    }
+
+    /// Iterates over the bytes in order.
+    fn bytes(&self) -> Bytes<'_, Self> {
+        Bytes {
+            bundle: self,
+            idx: 0,
+        }
+    }
 }
 
 impl ByteBundle for [u8] {
@@ -28,8 +243,39 @@ impl ByteBundle for [u8] {
     fn length(&self) -> usize {
         self.len()
     }
+
+    fn copy_to(&self, dst: &mut [u8]) {
+        dst.copy_from_slice(self);
+    }
+}
+
+/// An iterator over the bytes in a `ByteBundle`. Obtained from `ByteBundle::bytes`.
+pub struct Bytes<'a, B: ByteBundle + ?Sized> {
+    bundle: &'a B,
+    idx: usize,
+}
+
+impl<B: ByteBundle + ?Sized> Iterator for Bytes<'_, B> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.idx == self.bundle.length() {
+            None
+        } else {
+            let byte = self.bundle.get(self.idx);
+            self.idx += 1;
+            Some(byte)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.bundle.length() - self.idx;
+        (len, Some(len))
+    }
 }
 
+impl<B: ByteBundle + ?Sized> ExactSizeIterator for Bytes<'_, B> {}
+
 /// Represents a slice of bytes stored in the PROGMEM memory space.
 ///
 /// Runtime representation should be basically the same as a slice. I chose not to include
@@ -51,6 +297,24 @@ impl PGMSlice {
         self.len
     }
 
+    /// Iterates over the bytes, without the bounds check done by `get`.
+    pub fn iter(&self) -> PGMIter {
+        PGMIter {
+            addr: self.addr,
+            len: self.len,
+        }
+    }
+
+    /// Returns a sub-slice of this slice. Panics if the range is out of bounds.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> PGMSlice {
+        let (start, end) = bounds(range, self.len);
+
+        PGMSlice {
+            addr: unsafe { self.addr.add(start) },
+            len: end - start,
+        }
+    }
+
     pub fn chunks(&self, len: usize) -> PGMChunks {
         PGMChunks {
             slice: *self,
@@ -59,67 +323,408 @@ impl PGMSlice {
     }
 }
 
+impl SliceBundle for PGMSlice {
+    fn split_at(&self, mid: usize) -> (Self, Self) {
+        (self.slice(..mid), self.slice(mid..))
+    }
+}
+
 impl ByteBundle for PGMSlice {
     fn get(&self, idx: usize) -> u8 {
         if idx >= self.len {
             panic!("Index out of bounds: {}", idx);
         }
 
-        let idx = idx as isize;
+        unsafe { read_byte(self.addr.offset(idx as isize)) }
+    }
 
-        unsafe {
-            let addr = self.addr.offset(idx) as usize;
-            let out;
+    fn length(&self) -> usize {
+        self.len()
+    }
 
-            llvm_asm! {
-                "lpm $0, Z"
-                : "=r"(out)
-                : "{r31}"(addr >> 8),"{r30}"(addr)
-                : "r31", "r30"
-                : "volatile"
-            }
+    fn copy_to(&self, dst: &mut [u8]) {
+        assert_eq!(self.len, dst.len(), "Mismatched lengths");
 
-            out
+        unsafe { copy_bytes(self.addr, dst.as_mut_ptr(), self.len) }
+    }
+}
+
+/// Converts a range into start and end indices, panicking if it's out of bounds.
+fn bounds<R: RangeBounds<usize>>(range: R, len: usize) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end + 1,
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+
+    if start > end || end > len {
+        panic!("Range out of bounds: {}..{}", start, end);
+    }
+
+    (start, end)
+}
+
+/// An iterator over the bytes in a `PGMSlice`. Obtained from `PGMSlice::iter`.
+pub struct PGMIter {
+    addr: *const u8,
+    len: usize,
+}
+
+impl Iterator for PGMIter {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                let byte = read_byte(self.addr);
+                self.addr = self.addr.add(1);
+                self.len -= 1;
+                Some(byte)
+            }
         }
     }
 
-    fn length(&self) -> usize {
-        self.len()
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
     }
 }
 
+impl ExactSizeIterator for PGMIter {}
+
+/// A `ByteBundle` that can be split in two, which is all `PGMChunks` needs from it.
+pub trait SliceBundle: ByteBundle + Copy {
+    /// Splits the bundle into the bytes before `mid`, and the bytes from `mid` onwards.
+    fn split_at(&self, mid: usize) -> (Self, Self);
+}
+
 /// I want to send an entire screen's worth of pixel data (1kb) at the screen, but my TWI buffer is
 /// only 32 bytes long. Being able to split the PGMSlice into chunks like you can with a slice so I
 /// can use the usual iterator stuff would make that a lot easier.
-pub struct PGMChunks {
-    slice: PGMSlice,
+///
+/// The last chunk is shorter if the length doesn't divide evenly.
+pub struct PGMChunks<S = PGMSlice> {
+    slice: S,
     chunk_len: usize,
 }
 
-impl Iterator for PGMChunks {
-    type Item = PGMSlice;
+impl<S: SliceBundle> Iterator for PGMChunks<S> {
+    type Item = S;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.slice.len == 0 {
+        let len = self.slice.length();
+        if len == 0 {
             None
-        } else if self.slice.len < self.chunk_len {
-            let ret = self.slice;
-            self.slice.len = 0; // No need to faff around with the pointer here.
-            Some(ret)
         } else {
-            let mut ret_slice = self.slice;
-            ret_slice.len = self.chunk_len;
+            let (chunk, rest) = self.slice.split_at(self.chunk_len.min(len));
+            self.slice = rest;
+            Some(chunk)
+        }
+    }
+}
 
-            // We need to adjust the pointer of our slice, as well as its length, to account
-            // for the chunk we just removed.
-            unsafe {
-                let offset = self.chunk_len as isize;
+#[cfg(feature = "atmega2560")]
+pub use far::{FarAddr, FarPGMChunks, FarPGMSlice};
+
+/// Access to PROGMEM beyond the first 64KB, on the devices with more flash than that.
+///
+/// Pointers are only 16 bits, so they can't address the upper part of the flash. Instead, far
+/// data is addressed with a 24-bit address held in a `u32`, with the top byte loaded into
+/// RAMPZ and read with `ELPM`.
+///
+/// The interrupt handlers don't save RAMPZ, so far data mustn't be read inside one.
+#[cfg(feature = "atmega2560")]
+#[macro_use]
+mod far {
+    use super::{bounds, ByteBundle, PGMChunks, SliceBundle};
+    #[cfg(not(test))]
+    use crate::hal::{device::cpu::RAMPZ, register::Register};
+    use core::ops::RangeBounds;
+
+    /// A full PROGMEM address. The host's pointers don't fit in 24 bits, so when testing it's
+    /// a RAM address instead.
+    #[cfg(not(test))]
+    pub type FarAddr = u32;
+    #[cfg(test)]
+    pub type FarAddr = usize;
+
+    /// Declares byte arrays stored in the upper part of PROGMEM, and a function returning a
+    /// `FarPGMSlice` of each.
+    ///
+    /// The data is put in the `.progmemx.data` section, which the linker places after all of
+    /// the code, out of the way of the data that needs to be read with `LPM`. The array is
+    /// given an unmangled symbol of the same name, as that's the only way to get the full
+    /// address of it, so the name must be unique in the program.
+    ///
+    /// ```
+    /// far_progmem! {
+    ///     pub static DUNGEON_TILES: [u8; 4096] = [...];
+    /// }
+    ///
+    /// display.draw_tile_at(&mut twi, x, y, id, &DUNGEON_TILES().slice(0..9))?;
+    /// ```
+    #[macro_export]
+    macro_rules! far_progmem {
+        () => {};
+
+        (
+            $(#[$attr:meta])*
+            $vis:vis static $name:ident : [u8; $len:expr] = $value:expr;
+            $($rest:tt)*
+        ) => {
+            $(#[$attr])*
+            #[allow(non_snake_case)]
+            #[inline(always)]
+            $vis fn $name() -> crate::hal::progmem::FarPGMSlice {
+                #[link_section = ".progmemx.data"]
+                #[no_mangle]
+                #[used]
+                static $name: [u8; $len] = $value;
+
+                #[cfg(not(test))]
+                let addr = {
+                    let (lo, hi, hh): (u8, u8, u8);
+                    unsafe {
+                        llvm_asm! {
+                            concat!(
+                                "ldi $0, lo8(", stringify!($name), ")
+                                ldi $1, hi8(", stringify!($name), ")
+                                ldi $2, hh8(", stringify!($name), ")"
+                            )
+                            : "=d"(lo), "=d"(hi), "=d"(hh)
+                        }
+                    }
+
+                    (hh as u32) << 16 | (hi as u32) << 8 | lo as u32
+                };
+
+                // The host doesn't have separate program memory, so the array's just in RAM.
+                #[cfg(test)]
+                let addr = $name.as_ptr() as crate::hal::progmem::FarAddr;
+
+                // SAFETY: The address was just taken from the array in PROGMEM.
+                unsafe { crate::hal::progmem::FarPGMSlice::from_raw_parts(addr, $len) }
+            }
+
+            far_progmem! { $($rest)* }
+        };
+    }
+
+    /// Reads a single byte from anywhere in PROGMEM.
+    ///
+    /// SAFETY: The address must be in PROGMEM.
+    unsafe fn read_byte(addr: FarAddr) -> u8 {
+        #[cfg(test)]
+        return (addr as *const u8).read();
+
+        #[cfg(not(test))]
+        {
+            RAMPZ::set_raw_value((addr >> 16) as u8);
+            let addr = addr as u16;
+            let out;
+
+            llvm_asm! {
+                "elpm $0, Z"
+                : "=r"(out)
+                : "{r31}"(addr >> 8),"{r30}"(addr)
+                : "r31", "r30"
+                : "volatile"
+            }
+
+            out
+        }
+    }
+
+    /// The far version of `copy_bytes`, which skips zero lengths for the same reason. `ELPM Z+`
+    /// increments RAMPZ as well, so the data may cross a 64KB boundary.
+    ///
+    /// SAFETY: `src` must be in PROGMEM, and both must be valid for `len` bytes.
+    unsafe fn copy_bytes(src: FarAddr, dst: *mut u8, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        #[cfg(test)]
+        dst.copy_from_nonoverlapping(src as *const u8, len);
 
-                self.slice.addr = self.slice.addr.offset(offset);
-                self.slice.len -= self.chunk_len;
+        #[cfg(not(test))]
+        {
+            RAMPZ::set_raw_value((src >> 16) as u8);
+            let src = src as u16;
+            let dst = dst as usize;
+
+            llvm_asm! {
+                "1: elpm r0, Z+
+                st X+, r0
+                sbiw r24, 1
+                brne 1b"
+                :
+                : "{r31}"(src >> 8),"{r30}"(src),"{r27}"(dst >> 8),"{r26}"(dst),"{r25}"(len >> 8),"{r24}"(len)
+                : "r0", "r24", "r25", "r26", "r27", "r30", "r31", "memory"
+                : "volatile"
+            }
+        }
+    }
+
+    /// A slice of bytes stored anywhere in PROGMEM. The far equivalent of `PGMSlice`.
+    #[derive(Copy, Clone)]
+    pub struct FarPGMSlice {
+        addr: FarAddr,
+        len: usize,
+    }
+
+    impl FarPGMSlice {
+        /// SAFETY: The address MUST be in PROGMEM. Normally this is obtained from a function
+        /// declared with `far_progmem!`.
+        pub const unsafe fn from_raw_parts(addr: FarAddr, len: usize) -> Self {
+            Self { addr, len }
+        }
+
+        pub const fn len(&self) -> usize {
+            self.len
+        }
+
+        /// Returns a sub-slice of this slice. Panics if the range is out of bounds.
+        pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> FarPGMSlice {
+            let (start, end) = bounds(range, self.len);
+
+            FarPGMSlice {
+                addr: self.addr + start as FarAddr,
+                len: end - start,
             }
+        }
+
+        pub fn chunks(&self, len: usize) -> FarPGMChunks {
+            FarPGMChunks {
+                slice: *self,
+                chunk_len: len,
+            }
+        }
+    }
+
+    impl ByteBundle for FarPGMSlice {
+        fn get(&self, idx: usize) -> u8 {
+            if idx >= self.len {
+                panic!("Index out of bounds: {}", idx);
+            }
+
+            unsafe { read_byte(self.addr + idx as FarAddr) }
+        }
+
+        fn length(&self) -> usize {
+            self.len
+        }
+
+        fn copy_to(&self, dst: &mut [u8]) {
+            assert_eq!(self.len, dst.len(), "Mismatched lengths");
+
+            unsafe { copy_bytes(self.addr, dst.as_mut_ptr(), self.len) }
+        }
+    }
 
-            Some(ret_slice)
+    impl SliceBundle for FarPGMSlice {
+        fn split_at(&self, mid: usize) -> (Self, Self) {
+            (self.slice(..mid), self.slice(mid..))
         }
     }
+
+    /// Splits a `FarPGMSlice` into chunks.
+    pub type FarPGMChunks = PGMChunks<FarPGMSlice>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Monster {
+        hp: u8,
+        damage: u16,
+    }
+
+    progmem! {
+        static TABLE: [u16; 3] = [1, 0x1234, 0xFFFF];
+        static NAME: [u8; 3] = *b"Rat";
+        static RAT: Monster = Monster { hp: 3, damage: 0x0102 };
+    }
+
+    #[test]
+    fn loads_values_and_arrays() {
+        assert_eq!(TABLE.len(), 3);
+        assert_eq!(TABLE.load(1), 0x1234);
+        assert_eq!(TABLE.load(2), 0xFFFF);
+        assert_eq!(
+            RAT.load(),
+            Monster {
+                hp: 3,
+                damage: 0x0102
+            }
+        );
+
+        let name = NAME.as_slice();
+        assert_eq!(name.length(), 3);
+        assert_eq!(name.get(2), b't');
+    }
+
+    #[test]
+    fn slices_iterate_and_copy() {
+        let all = NAME.as_slice();
+        assert!(all.iter().eq(b"Rat".iter().copied()));
+        assert!(all.bytes().eq(b"Rat".iter().copied()));
+
+        let tail = all.slice(1..);
+        assert!(tail.iter().eq(b"at".iter().copied()));
+        assert_eq!(all.slice(..=1).len(), 2);
+        assert_eq!(all.slice(3..).len(), 0);
+
+        let mut buf = [0; 2];
+        tail.copy_to(&mut buf);
+        assert_eq!(&buf, b"at");
+    }
+
+    #[test]
+    fn chunks_split_evenly_then_leave_the_rest() {
+        let chunks: Vec<_> = NAME.as_slice().chunks(2).collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].iter().eq(b"Ra".iter().copied()));
+        assert!(chunks[1].iter().eq(b"t".iter().copied()));
+
+        assert_eq!(NAME.as_slice().chunks(3).count(), 1);
+        assert_eq!(NAME.as_slice().slice(3..).chunks(2).count(), 0);
+    }
+
+    #[cfg(feature = "atmega2560")]
+    far_progmem! {
+        static FAR_TEST_DATA: [u8; 6] = *b"Goblin";
+    }
+
+    #[cfg(feature = "atmega2560")]
+    #[test]
+    fn far_slices_read_copy_and_chunk() {
+        let all = FAR_TEST_DATA();
+        assert_eq!(all.len(), 6);
+        assert_eq!(all.get(0), b'G');
+        assert_eq!(all.get(5), b'n');
+
+        let middle = all.slice(1..4);
+        assert_eq!(middle.len(), 3);
+        assert_eq!(middle.get(2), b'l');
+        assert!(middle.bytes().eq(b"obl".iter().copied()));
+
+        let mut buf = [0; 3];
+        middle.copy_to(&mut buf);
+        assert_eq!(&buf, b"obl");
+        all.slice(6..).copy_to(&mut []);
+
+        let chunks: Vec<_> = all.chunks(4).collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].bytes().eq(b"Gobl".iter().copied()));
+        assert!(chunks[1].bytes().eq(b"in".iter().copied()));
+    }
 }
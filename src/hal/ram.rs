@@ -0,0 +1,152 @@
+//! Keeps track of how much of the RAM is in use.
+//!
+//! The statics take up a fixed amount at the start of the RAM, which is known once the binary
+//! is linked, while the stack grows down from the end towards them. At boot everything between
+//! the two is painted with a known byte, so the deepest the stack has been can be found later
+//! by looking at how much of the paint is left.
+//!
+//! This is a lower bound, as the stack may have written a byte that happened to match the paint.
+//! There's no heap, so nothing else should touch the painted area.
+
+use crate::hal::{
+    device::{RAM_SIZE, RAM_START},
+    usart::USART,
+};
+
+/// What the unused RAM is painted with.
+const PAINT: u8 = 0xC5;
+
+/// The address just past the end of the RAM, where the stack starts.
+const RAM_END: u16 = RAM_START + RAM_SIZE;
+
+#[cfg(not(test))]
+extern "C" {
+    // Provided by avr-libc's linker script. Only their addresses mean anything.
+    static __data_start: u8;
+    static __data_end: u8;
+    static __bss_start: u8;
+    static __bss_end: u8;
+    static __heap_start: u8;
+}
+
+/// Where the linker put the statics.
+struct Layout {
+    data_start: u16,
+    data_end: u16,
+    bss_start: u16,
+    bss_end: u16,
+    /// The first byte after all the statics, and the start of the painted area.
+    free_start: u16,
+}
+
+impl Layout {
+    #[cfg(not(test))]
+    fn get() -> Layout {
+        unsafe {
+            Layout {
+                data_start: &__data_start as *const u8 as u16,
+                data_end: &__data_end as *const u8 as u16,
+                bss_start: &__bss_start as *const u8 as u16,
+                bss_end: &__bss_end as *const u8 as u16,
+                free_start: &__heap_start as *const u8 as u16,
+            }
+        }
+    }
+
+    // There's no linker script on the host, so pretend the statics fill the RAM.
+    #[cfg(test)]
+    fn get() -> Layout {
+        Layout {
+            data_start: RAM_START,
+            data_end: RAM_END,
+            bss_start: RAM_END,
+            bss_end: RAM_END,
+            free_start: RAM_END,
+        }
+    }
+}
+
+/// Paints the RAM between the statics and the top of the stack.
+///
+/// The loop is in assembly as it's writing right up to the stack pointer, so it mustn't push
+/// anything, or call `memset`.
+///
+/// SAFETY: Interrupts must be disabled, as an interrupt handler's stack frame would be painted
+/// over.
+pub unsafe fn paint_stack() {
+    #[cfg(not(test))]
+    {
+        let start = Layout::get().free_start;
+
+        // 0x3D and 0x3E are SPL and SPH in the IO space.
+        llvm_asm! {
+            "in r24, 0x3D
+            in r25, 0x3E
+            1: st Z+, $0
+            cp r30, r24
+            cpc r31, r25
+            brlo 1b"
+            :
+            : "r"(PAINT),"{r31}"(start >> 8),"{r30}"(start)
+            : "r24", "r25", "r30", "r31", "memory"
+            : "volatile"
+        }
+    }
+}
+
+/// Returns how many bytes of paint are left starting from `start`, stopping at `end`.
+///
+/// SAFETY: `start..end` must be valid to read.
+unsafe fn painted_len(start: *const u8, end: *const u8) -> u16 {
+    let mut cur = start;
+    while cur < end && *cur == PAINT {
+        cur = cur.add(1);
+    }
+
+    (cur as usize - start as usize) as u16
+}
+
+/// Returns the most stack that's been used since `paint_stack` was called, in bytes.
+pub fn stack_peak() -> u16 {
+    let free_start = Layout::get().free_start;
+    let painted = unsafe { painted_len(free_start as *const u8, RAM_END as *const u8) };
+
+    RAM_END - free_start - painted
+}
+
+/// Sends a summary of the RAM usage over the USART.
+pub fn ram_report(usart: &mut USART) {
+    let layout = Layout::get();
+    let stack = stack_peak();
+
+    usart.send("RAM: ");
+    usart.send_decimal(RAM_SIZE);
+    usart.send("\r\n.data: ");
+    usart.send_decimal(layout.data_end - layout.data_start);
+    usart.send("\r\n.bss: ");
+    usart.send_decimal(layout.bss_end - layout.bss_start);
+    usart.send("\r\nStack peak: ");
+    usart.send_decimal(stack);
+    usart.send("\r\nFree: ");
+    usart.send_decimal(RAM_END - layout.free_start - stack);
+    usart.send("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_first_overwritten_byte() {
+        let mut ram = [PAINT; 32];
+        // The stack's grown down to index 20, with one byte that happens to match the paint.
+        ram[20..].copy_from_slice(&[1, 2, PAINT, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+
+        let range = ram.as_ptr_range();
+        assert_eq!(unsafe { painted_len(range.start, range.end) }, 20);
+
+        let ram = [PAINT; 8];
+        let range = ram.as_ptr_range();
+        assert_eq!(unsafe { painted_len(range.start, range.end) }, 8);
+    }
+}
@@ -1,6 +1,6 @@
 use core::{
     marker::PhantomData,
-    ops::{BitAnd, BitOr, BitOrAssign, Not, Shl},
+    ops::{BitAnd, BitOr, BitOrAssign, Not, Shl, Shr},
 };
 
 /// Used to restrict what data types a register can be.
@@ -12,6 +12,7 @@ pub trait RegisterType:
     + BitAnd<Output = Self>
     + BitOr<Output = Self>
     + Shl<Output = Self>
+    + Shr<Output = Self>
     + Not<Output = Self>
     + Eq
     + PartialEq
@@ -85,6 +86,43 @@ pub trait Bit {
     fn bit_id(&self) -> <Self::Register as Register>::DataType;
 }
 
+/// Converts between the raw value of a multi-bit field and the enum representing it.
+///
+/// The raw value is shifted down to start at bit 0, so the values match the tables in the
+/// datasheet.
+pub trait FieldValue<T>: Copy {
+    fn from_raw(raw: T) -> Self;
+    fn raw(self) -> T;
+}
+
+/// Represents a multi-bit field in a register, such as a clock select or a status code.
+///
+/// As with `Bit`, a field is associated with its parent register and its read/write access, and
+/// additionally with the enum representing its possible values.
+pub trait Field {
+    type ReadAccess: Access;
+    type WriteAccess: Access;
+    type Register: Register;
+    type Value: FieldValue<<Self::Register as Register>::DataType>;
+
+    /// The position of the lowest bit of the field.
+    fn offset(&self) -> <Self::Register as Register>::DataType;
+    /// All of the bits in the field, in position.
+    fn mask(&self) -> <Self::Register as Register>::DataType;
+
+    /// Returns the field set to the given value, so it can be written with `set_value` or ORed
+    /// with other bits from the register.
+    fn with(
+        &self,
+        value: Self::Value,
+    ) -> BitBuilder<Self::Register, Self::ReadAccess, Self::WriteAccess> {
+        BitBuilder {
+            data: (value.raw() << self.offset()) & self.mask(),
+            _p: PhantomData,
+        }
+    }
+}
+
 /// The BitBuilder allows the user to write expressions like `Bit1 | Bit2` to build up a bit pattern, while
 /// still retaining the connection to the register and the restrictions on read/write access.
 pub struct BitBuilder<Reg: Register, R: Access, W: Access> {
@@ -233,7 +271,12 @@ pub trait Register: Sized {
     const WRITE_MASK: Self::DataType;
 
     unsafe fn set_raw_value(val: Self::DataType) {
+        #[cfg(not(test))]
         Self::ADDR.write_volatile(val & Self::WRITE_MASK);
+
+        // There's no hardware to write to on the host, so tests use a simulated register file.
+        #[cfg(test)]
+        crate::hal::sim::write(Self::ADDR as usize, val, Self::WRITE_MASK);
     }
 
     unsafe fn set_value<V>(val: V)
@@ -245,7 +288,11 @@ pub trait Register: Sized {
     }
 
     unsafe fn get_value() -> Self::DataType {
-        Self::ADDR.read_volatile()
+        #[cfg(not(test))]
+        return Self::ADDR.read_volatile();
+
+        #[cfg(test)]
+        return crate::hal::sim::read(Self::ADDR as usize);
     }
 
     unsafe fn get_bit<B>(bit: B) -> bool
@@ -273,6 +320,23 @@ pub trait Register: Sized {
         Self::set_raw_value(val & !bits.value());
     }
 
+    unsafe fn get_field<F>(field: F) -> F::Value
+    where
+        F: Field<Register = Self, ReadAccess = Readable>,
+    {
+        let raw = (Self::get_value() & field.mask()) >> field.offset();
+        F::Value::from_raw(raw)
+    }
+
+    /// Replaces the value of a single field, leaving the rest of the register as it was.
+    unsafe fn set_field<F>(field: F, value: F::Value)
+    where
+        F: Field<Register = Self, ReadAccess = Readable, WriteAccess = Writable>,
+    {
+        let val = Self::get_value() & !field.mask();
+        Self::set_raw_value(val | field.with(value).data);
+    }
+
     unsafe fn replace_bits(
         mask: BitBuilder<Self, Readable, Writable>,
         new_val: BitBuilder<Self, Readable, Writable>,
@@ -367,6 +431,80 @@ macro_rules! reg_named_bits {
     };
 }
 
+/// Declares the multi-bit fields of a register in the same way as `reg_named_bits` does for
+/// single bits. Each field covers an inclusive range of bits, and has an enum for its values.
+#[macro_export]
+macro_rules! reg_named_fields {
+    (
+        $reg:ident : $type:ty {
+            $( $(#[$field_doc:meta])* $field:ident = $lo:literal ..= $hi:literal => $value:ty, $acc:tt;)+
+        }
+    ) => {
+        $(
+            $(#[$field_doc])*
+            #[derive(Copy, Clone)]
+            pub struct $field;
+            impl crate::hal::register::Field for $field {
+                type Register = $reg;
+                type Value = $value;
+                expand_read_access!{$acc}
+                expand_write_access!{$acc}
+
+                fn offset(&self) -> $type {
+                    $lo
+                }
+
+                fn mask(&self) -> $type {
+                    // Shift the ones down to the width of the field, then up into position.
+                    let width = $hi - $lo + 1;
+                    let unused_bits = core::mem::size_of::<$type>() as $type * 8 - width;
+                    (<$type>::max_value() >> unused_bits) << $lo
+                }
+            }
+        )+
+    };
+}
+
+/// Declares an enum for the values of a multi-bit field, and implements `FieldValue` for it.
+///
+/// Any raw values not listed, such as reserved combinations, are converted to the variant
+/// given by the final `_ =>` line.
+#[macro_export]
+macro_rules! field_enum {
+    (
+        $(#[$enum_doc:meta])*
+        $name:ident : $type:ident {
+            $( $(#[$variant_doc:meta])* $variant:ident = $value:expr, )+
+            _ => $fallback:ident $(,)*
+        }
+    ) => {
+        $(#[$enum_doc])*
+        #[derive(Copy, Clone, Eq, PartialEq)]
+        #[repr($type)]
+        pub enum $name {
+            $( $(#[$variant_doc])* $variant = $value, )+
+        }
+
+        impl crate::hal::register::FieldValue<$type> for $name {
+            #[allow(non_upper_case_globals)]
+            fn from_raw(raw: $type) -> Self {
+                // Constants can be used as patterns, which lets the compiler build a proper
+                // match instead of a chain of comparisons.
+                $( const $variant: $type = $value; )+
+
+                match raw {
+                    $( $variant => $name::$variant, )+
+                    _ => $name::$fallback,
+                }
+            }
+
+            fn raw(self) -> $type {
+                self as $type
+            }
+        }
+    };
+}
+
 /// There are a lot of bits, with rather opaque names. This macro defines constants on the parent register that
 /// allow the user to find the bit through the register.
 #[macro_export]
@@ -412,16 +550,41 @@ macro_rules! reg {
         $name:ident: $type:ty {
             addr: $addr:expr,
             write mask: $mask:expr,
-            bits: {
+            $(bits: {
                 $( $(#[$bit_doc:meta])* $bit:ident = $id:expr, $acc:tt;)+
-            }
+            })?
+            $(fields: {
+                $( $(#[$field_doc:meta])* $field:ident = $lo:literal ..= $hi:literal => $value:ty, $field_acc:tt;)+
+            })?
         }
     ) => {
-        reg_named_bits! {
-            $name: $type {
-                $( $(#[$bit_doc])* $bit = $id, $acc; )+
+        $(
+            reg_named_bits! {
+                $name: $type {
+                    $( $(#[$bit_doc])* $bit = $id, $acc; )+
+                }
+            }
+
+            reg_bit_consts! {
+                $name {
+                    $( $(#[$bit_doc])* $bit ),+
+                }
+            }
+        )?
+
+        $(
+            reg_named_fields! {
+                $name: $type {
+                    $( $(#[$field_doc])* $field = $lo..=$hi => $value, $field_acc; )+
+                }
             }
-        }
+
+            reg_bit_consts! {
+                $name {
+                    $( $(#[$field_doc])* $field ),+
+                }
+            }
+        )?
 
         reg! {
             $(#[$reg_doc])*
@@ -430,11 +593,5 @@ macro_rules! reg {
                 write mask: $mask,
             }
         }
-
-        reg_bit_consts! {
-            $name {
-                $( $(#[$bit_doc])* $bit ),+
-            }
-        }
     };
 }
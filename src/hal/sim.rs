@@ -0,0 +1,228 @@
+//! A simulated register file, which stands in for the hardware when running tests on the host.
+//!
+//! Under `cfg(test)` the `Register` trait reads and writes this instead of the fixed MMIO
+//! addresses. Each test thread gets its own register file, so tests don't see each other's
+//! writes, but the drivers' global state is shared, so tests must hold the guard returned by
+//! `reset` while they run.
+//!
+//! Writes can be hooked to simulate the side effects the hardware would have, such as writing
+//! to a PIN register toggling the PORT register. Hooks can also raise interrupts, which are
+//! run once the global interrupt flag in SREG allows it, the same as on the real thing.
+
+use crate::hal::{
+    interrupt::registers::SREG,
+    register::{Register, RegisterType},
+};
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+/// The size of the data space that holds the registers.
+const DATA_SPACE_SIZE: usize = 0x200;
+
+/// The global interrupt enable bit in SREG.
+const SREG_I: u8 = 1 << 7;
+
+/// Called in place of the store when a byte is written to a hooked address. The hook is given
+/// the value after the write mask has been applied, and is responsible for storing it.
+///
+/// Bits outside of the write mask keep their current value, as read-only bits would.
+pub type WriteHook = Box<dyn FnMut(&mut Hardware, u8)>;
+
+/// The state of the simulated hardware, as seen by the write hooks.
+///
+/// Hooks are called while the register file is borrowed, so they must go through this rather
+/// than the `Register` trait.
+pub struct Hardware {
+    /// The register file, indexed by data space address.
+    pub data: [u8; DATA_SPACE_SIZE],
+    pending: VecDeque<unsafe fn()>,
+}
+
+impl Hardware {
+    /// Gets a register's current value.
+    pub fn get<R: Register>(&self) -> R::DataType {
+        read_from(&self.data, R::ADDR as usize)
+    }
+
+    /// Sets a register's value, bypassing the write mask and any hooks.
+    pub fn set<R: Register>(&mut self, val: R::DataType) {
+        let addr = R::ADDR as usize;
+        let bytes = to_bytes(val);
+        self.data[addr..addr + bytes.len()].copy_from_slice(&bytes);
+    }
+
+    /// Marks an interrupt as pending. Takes the handler given to the `interrupt` macro, as the
+    /// vector's name depends on the device.
+    pub fn raise(&mut self, vector: unsafe fn()) {
+        self.pending.push_back(vector);
+    }
+}
+
+struct State {
+    hw: Hardware,
+    hooks: HashMap<usize, WriteHook>,
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            hw: Hardware {
+                data: [0; DATA_SPACE_SIZE],
+                pending: VecDeque::new(),
+            },
+            hooks: HashMap::new(),
+        }
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::new());
+}
+
+/// Whether a test is currently using the hardware.
+static IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Keeps other tests from using the hardware while held.
+pub struct Guard {
+    _p: (),
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        IN_USE.store(false, Ordering::Release);
+    }
+}
+
+/// Clears the register file and any hooks, after waiting for any other test using the
+/// hardware to finish.
+pub fn reset() -> Guard {
+    while IN_USE
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        thread::yield_now();
+    }
+
+    STATE.with(|s| *s.borrow_mut() = State::new());
+    install_port_hooks();
+
+    Guard { _p: () }
+}
+
+/// Writing a 1 to a bit in a PIN register toggles that bit in the PORT register, which is
+/// two addresses up.
+fn install_port_hooks() {
+    use crate::hal::ports::registers::{PINB, PINC, PIND};
+
+    for &pin in &[
+        PINB::ADDR as usize,
+        PINC::ADDR as usize,
+        PIND::ADDR as usize,
+    ] {
+        on_write_addr(pin, move |hw, val| hw.data[pin + 2] ^= val);
+    }
+}
+
+/// Hooks writes to an 8-bit register.
+pub fn on_write<R, F>(hook: F)
+where
+    R: Register<DataType = u8>,
+    F: FnMut(&mut Hardware, u8) + 'static,
+{
+    on_write_addr(R::ADDR as usize, hook);
+}
+
+fn on_write_addr<F>(addr: usize, hook: F)
+where
+    F: FnMut(&mut Hardware, u8) + 'static,
+{
+    STATE.with(|s| s.borrow_mut().hooks.insert(addr, Box::new(hook)));
+}
+
+/// Sets a register's value, bypassing the write mask and any hooks. Used for setting up the
+/// bits only the hardware can write, such as status flags.
+pub fn set<R: Register>(val: R::DataType) {
+    STATE.with(|s| s.borrow_mut().hw.set::<R>(val));
+}
+
+/// Gets a register's current value.
+pub fn get<R: Register>() -> R::DataType {
+    STATE.with(|s| s.borrow().hw.get::<R>())
+}
+
+pub(crate) fn write<T: RegisterType>(addr: usize, val: T, mask: T) {
+    STATE.with(|s| {
+        let state = &mut *s.borrow_mut();
+        let val = (read_from::<T>(&state.hw.data, addr) & !mask) | (val & mask);
+        let bytes = to_bytes(val);
+
+        // The high byte of a 16-bit register must be written first.
+        for (i, &byte) in bytes.iter().enumerate().rev() {
+            match state.hooks.get_mut(&(addr + i)) {
+                Some(hook) => hook(&mut state.hw, byte),
+                None => state.hw.data[addr + i] = byte,
+            }
+        }
+    });
+
+    // Either a hook raised an interrupt, or this write enabled them.
+    run_pending_interrupts();
+}
+
+pub(crate) fn read<T: RegisterType>(addr: usize) -> T {
+    STATE.with(|s| read_from(&s.borrow().hw.data, addr))
+}
+
+/// Runs any pending interrupts, if they're enabled.
+///
+/// Like the hardware, interrupts are disabled while the handler runs, so any raised by the
+/// handler are left pending until it returns.
+fn run_pending_interrupts() {
+    let sreg = SREG::ADDR as usize;
+
+    loop {
+        let vector = STATE.with(|s| {
+            let hw = &mut s.borrow_mut().hw;
+            if hw.data[sreg] & SREG_I == 0 {
+                return None;
+            }
+
+            let vector = hw.pending.pop_front()?;
+            hw.data[sreg] &= !SREG_I;
+            Some(vector)
+        });
+
+        match vector {
+            Some(vector) => {
+                unsafe { vector() };
+                STATE.with(|s| s.borrow_mut().hw.data[sreg] |= SREG_I);
+            }
+            None => break,
+        }
+    }
+}
+
+fn read_from<T: RegisterType>(data: &[u8], addr: usize) -> T {
+    let mut val = T::ZERO;
+
+    // SAFETY: The register types are plain integers, and the host is little-endian like the AVR.
+    unsafe {
+        let dst = &mut val as *mut T as *mut u8;
+        dst.copy_from_nonoverlapping(data[addr..].as_ptr(), mem::size_of::<T>());
+    }
+
+    val
+}
+
+fn to_bytes<T: RegisterType>(val: T) -> Vec<u8> {
+    // SAFETY: See `read_from`.
+    unsafe {
+        std::slice::from_raw_parts(&val as *const T as *const u8, mem::size_of::<T>()).to_vec()
+    }
+}
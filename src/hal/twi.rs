@@ -23,158 +23,108 @@
 #![allow(dead_code)]
 
 use crate::hal::{
-    ports::registers::{DDRC, PORTC},
+    interrupt::{self, Mutex},
+    ports::{Input, Pin, PinId, Port, PullUp},
     progmem::ByteBundle,
     register::Register,
     CPU_FREQ,
 };
+use core::cell::RefCell;
+
+/// The pins used by the TWI module.
+#[cfg(any(feature = "atmega328p", feature = "atmega168"))]
+pub use crate::hal::ports::{PC4 as SDA, PC5 as SCL};
+#[cfg(any(feature = "atmega32u4", feature = "atmega2560"))]
+pub use crate::hal::ports::{PD0 as SCL, PD1 as SDA};
 
 pub const BUFFER_LEN: usize = 32;
 const TWI_FREQ: u32 = 400_000;
-const TWI_BIT_RATE: u8 = ((CPU_FREQ / TWI_FREQ - 16) / 2) as u8;
+/// With a prescale of 1, SCL runs at `CPU_FREQ / (16 + 2 * TWBR)`.
+///
+/// Below 6.4MHz the CPU can't reach 400KHz, so the bit rate is 0, giving the fastest it can
+/// manage (62.5KHz at 1MHz).
+const fn twi_bit_rate(cpu_freq: u32) -> u8 {
+    if cpu_freq / TWI_FREQ > 16 {
+        ((cpu_freq / TWI_FREQ - 16) / 2) as u8
+    } else {
+        0
+    }
+}
+const TWI_BIT_RATE: u8 = twi_bit_rate(CPU_FREQ);
+
+// The bit rate doesn't fit in TWBR, so the bus would run far too fast.
+const_assert!(CPU_FREQ / TWI_FREQ <= 16 || (CPU_FREQ / TWI_FREQ - 16) / 2 <= 0xFF);
 
 pub mod registers {
-    reg! {
-        /// TWI Bit Rate Register
-        TWBR: u8 {
-            addr: 0xB8,
-            write mask: 0xFF,
+    pub use crate::hal::device::twi::*;
+
+    field_enum! {
+        /// The status codes reported by the TWI module.
+        ///
+        /// The datasheet lists the codes as the whole register value with the prescaler bits
+        /// masked off, so they're shifted down here to get the field's value.
+        /// See ATMega328P datasheet, section 21.7.
+        TWSRStatus: u8 {
+            // All Master
+            Start = 0x08 >> 3,
+            RepStart = 0x10 >> 3,
+
+            // Master Transmitter
+            MtSlaAck = 0x18 >> 3,
+            MtDataAck = 0x28 >> 3,
+            MtSlaNack = 0x20 >> 3,
+            MtDataNack = 0x30 >> 3,
+            MtArbLost = 0x38 >> 3,
+
+            // Master Receiver
+            MrDataAck = 0x50 >> 3,
+            MrSlaAck = 0x40 >> 3,
+            MrDataNack = 0x58 >> 3,
+            MrSlaNack = 0x48 >> 3,
+
+            // Slave Receiver
+            SrSlaAck = 0x60 >> 3,
+            SrGCallAck = 0x70 >> 3,
+            SrArbLostSlaAck = 0x68 >> 3,
+            SrArbLostGCallAck = 0x78 >> 3,
+            SrDataAck = 0x80 >> 3,
+            SrGCallDataAck = 0x90 >> 3,
+            SrStop = 0xA0 >> 3,
+            SrDataNack = 0x88 >> 3,
+            SrGCallDataNack = 0x98 >> 3,
+
+            // Slave Transmitter
+            StSlaAck = 0xA8 >> 3,
+            StArbLostSlaAck = 0xB0 >> 3,
+            StDataAck = 0xB8 >> 3,
+            StDataNack = 0xC0 >> 3,
+            StLastData = 0xC8 >> 3,
+
+            // All
+            NoInfo = 0xF8 >> 3,
+            BusError = 0x00 >> 3,
+
+            // The hardware never reports any other codes.
+            _ => NoInfo,
         }
     }
 
-    reg! {
-        /// TWI Status Register
-        TWSR: u8 {
-            addr: 0xB9,
-            write mask: 0b0000_0011,
-            bits: {
-                /// TWI Prescaler - Bit 0
-                TWPS0 = 0, RW;
-                /// TWI Prescaler - Bit 1
-                TWPS1 = 1, RW;
-                /// TWI Status - Bit 0
-                TWS0 = 3, R;
-                /// TWI Status - Bit 1
-                TWS1 = 4, R;
-                /// TWI Status - Bit 2
-                TWS2 = 5, R;
-                /// TWI Status - Bit 3
-                TWS3 = 6, R;
-                /// TWI Status - Bit 4
-                TWS4 = 7, R;
-            }
+    field_enum! {
+        /// The prescaler for the TWI bit rate generator.
+        TWIPrescaler: u8 {
+            Div1 = 0,
+            Div4 = 1,
+            Div16 = 2,
+            Div64 = 3,
+            _ => Div1,
         }
     }
 
-    // SAFETY: Must be a C-style enum, and MUST represent every state provided by the hardware.
-    // See ATMega328P datasheet, section 21.7.
-    #[derive(Copy, Clone, Eq, PartialEq)]
-    #[repr(u8)]
-    pub enum TWSRStatus {
-        // All Master
-        Start = 0x08,
-        RepStart = 0x10,
-
-        // Master Transmitter
-        MtSlaAck = 0x18,
-        MtDataAck = 0x28,
-        MtSlaNack = 0x20,
-        MtDataNack = 0x30,
-        MtArbLost = 0x38,
-
-        // Master Receiver
-        MrDataAck = 0x50,
-        MrSlaAck = 0x40,
-        MrDataNack = 0x58,
-        MrSlaNack = 0x48,
-
-        // Slave Receiver
-        SrSlaAck = 0x60,
-        SrGCallAck = 0x70,
-        SrArbLostSlaAck = 0x68,
-        SrArbLostGCallAck = 0x78,
-        SrDataAck = 0x80,
-        SrGCallDataAck = 0x90,
-        SrStop = 0xA0,
-        SrDataNack = 0x88,
-        SrGCallDataNack = 0x98,
-
-        // Slave Transmitter
-        StSlaAck = 0xA8,
-        StArbLostSlaAck = 0xB0,
-        StDataAck = 0xB8,
-        StDataNack = 0xC0,
-        StLastData = 0xC8,
-
-        // All
-        NoInfo = 0xF8,
-        BusError = 0x00,
-    }
-
     impl TWSR {
         pub fn status() -> TWSRStatus {
             use crate::hal::register::Register;
 
-            let mask = TWSR::TWS0 | TWSR::TWS1 | TWSR::TWS2 | TWSR::TWS3 | TWSR::TWS4;
-
-            // SAFETY: The returned enum encompasses all states the hardware can supply as per the
-            // ATMega328P datasheet, section 21.7.
-            unsafe {
-                let val = TWSR::get_value() & mask.raw_value();
-                core::mem::transmute(val)
-            }
-        }
-    }
-
-    reg! {
-        /// TWI (Slave) Address Register
-        TWAR: u8 {
-            addr: 0xBA,
-            write mask: 0xFF,
-            bits: {
-                /// TWI General Call Recognition Enable
-                TWGCE = 0, RW;
-            }
-        }
-    }
-
-    reg! {
-        /// TWI Data Register
-        TWDR: u8 {
-            addr: 0xBB,
-            write mask: 0xFF,
-        }
-    }
-
-    reg! {
-        /// TWI Control Register
-        TWCR: u8 {
-            addr: 0xBC,
-            write mask: 0b1111_0101,
-            bits: {
-                /// TWI Interrupt Enable
-                TWIE = 0, RW;
-                /// TWI Enable
-                TWEN = 2, RW;
-                /// TWI Write Collision Flag
-                TWWC = 3, R;
-                /// TWI Stop Condition
-                TWSTO = 4, RW;
-                /// TWI Start Condition
-                TWSTA = 5, RW;
-                /// TWI Enable Acknowledge
-                TWEA = 6, RW;
-                /// TWI Interrupt Flag
-                TWINT = 7, RW;
-            }
-        }
-    }
-
-    reg! {
-        /// TWI Slave Address Mask Register
-        TWAMR: u8 {
-            addr: 0xBD,
-            write mask: 0xFE,
+            unsafe { TWSR::get_field(TWSR::TWS) }
         }
     }
 }
@@ -201,14 +151,10 @@ impl Buffer {
         if BUFFER_LEN < data.length() {
             Err(TWIError::BufferLenError)
         } else {
-            unsafe {
-                // SAFETY: data.length() must never be greater than BUFFER_LEN.
-                for i in 0..data.length() {
-                    *self.buf.get_unchecked_mut(i) = data.get(i);
-                }
-                self.len = data.length() as u8;
-                self.idx = 0;
-            }
+            data.copy_to(&mut self.buf[..data.length()]);
+            self.len = data.length() as u8;
+            self.idx = 0;
+
             Ok(())
         }
     }
@@ -242,12 +188,10 @@ pub enum TWIError {
     NotReady,
     BusError,
 
-    InitError,
     InvalidAddress,
 }
 
 /// This type used to store the global data for communication between the interrupt and normal code.
-#[repr(C)]
 struct TWIGlobalData {
     state: TWIState,
     address: u8,
@@ -256,72 +200,66 @@ struct TWIGlobalData {
     buffer: Buffer,
 }
 
-/// Mmm.... boilerplate...
-///
-/// These functions are simple wrappers to make volatile reading and writing less messy.
-impl TWIGlobalData {
-    unsafe fn state(&mut self) -> TWIState {
-        (&mut self.state as *mut TWIState).read_volatile()
-    }
-
-    unsafe fn set_state(&mut self, new: TWIState) {
-        (&mut self.state as *mut TWIState).write_volatile(new);
-    }
-
-    unsafe fn error(&mut self) -> TWSRStatus {
-        (&mut self.error as *mut TWSRStatus).read_volatile()
-    }
-
-    unsafe fn set_error(&mut self, new: TWSRStatus) {
-        (&mut self.error as *mut TWSRStatus).write_volatile(new);
-    }
-}
-
 /// This is where we store the data shared between the interrupt and non-interrupt code.
-static mut TWI_GLOBAL: TWIGlobalData = TWIGlobalData {
+static TWI_GLOBAL: Mutex<RefCell<TWIGlobalData>> = Mutex::new(RefCell::new(TWIGlobalData {
     state: TWIState::None,
     address: 0,
     error: TWSRStatus::NoInfo,
     buffer: Buffer::new(),
-};
+}));
 
-/// Tracks whether the TWI has been initialized so only one TWI live at a time.
-static mut HAS_INIT: bool = false;
+/// Ownership token for the TWI module. Obtained from `Peripherals::take`.
+///
+/// The 328P only has the one TWI module, but it's numbered like the USART so it doesn't
+/// clash with the driver's name.
+pub struct TWI0 {
+    _p: (),
+}
+
+impl TWI0 {
+    /// SAFETY: Only one token for the TWI module may exist at a time.
+    pub unsafe fn steal() -> Self {
+        Self { _p: () }
+    }
+}
 
 /// A wrapper around the TWI module.
 ///
 /// Provides a simplified interface over the TWI module, which only allows the transmission
 /// of data as a master on the TWI bus.
 ///
-/// Because we're representing a hardware module, we should ensure that only one of these
-/// exists at any one time.
-pub struct TWI(core::marker::PhantomData<()>);
+/// Consumes the module's token and its SDA and SCL pins, so only one of these can exist at
+/// any one time.
+pub struct TWI {
+    _twi: TWI0,
+    _sda: Pin<SDA, Input<PullUp>>,
+    _scl: Pin<SCL, Input<PullUp>>,
+}
 
 impl TWI {
     /// Initializes the TWI module with a 1 prescale and 400KHz transmission rate.
     /// Sets the SDA and SCL pins to input, and enables the internal pullups.
     /// Enables the ACK pulse, and TWI interrupt.
-    pub fn init() -> Result<TWI, TWIError> {
-        unsafe {
-            if HAS_INIT {
-                Err(TWIError::InitError)
-            } else {
-                HAS_INIT = true;
-                TWI_GLOBAL.set_state(TWIState::Ready);
+    pub fn init<M1, M2>(twi: TWI0, sda: Pin<SDA, M1>, scl: Pin<SCL, M2>) -> TWI {
+        interrupt::free(|cs| TWI_GLOBAL.borrow(cs).borrow_mut().state = TWIState::Ready);
 
-                // Set the SDA and SCL pins to input, and enable the internal pullups.
-                DDRC::clear_bits(DDRC::DDRC4 | DDRC::DDRC5);
-                PORTC::set_bits(PORTC::PORTC4 | PORTC::PORTC5);
+        // Set the SDA and SCL pins to input, and enable the internal pullups.
+        let sda = sda.into_pull_up_input();
+        let scl = scl.into_pull_up_input();
 
-                // Initiliazing the prescaler to 1, and bit rate for a 400KHz transmission rate.
-                TWSR::clear_bits(TWSR::TWPS0 | TWSR::TWPS1);
-                TWBR::set_raw_value(TWI_BIT_RATE);
+        unsafe {
+            // Initiliazing the prescaler to 1, and bit rate for a 400KHz transmission rate.
+            TWSR::set_field(TWSR::TWPS, TWIPrescaler::Div1);
+            TWBR::set_raw_value(TWI_BIT_RATE);
 
-                // Enable the TWI module, and the ACK.
-                TWCR::set_value(TWCR::TWEN | TWCR::TWEA);
+            // Enable the TWI module, and the ACK.
+            TWCR::set_value(TWCR::TWEN | TWCR::TWEA);
+        }
 
-                Ok(TWI(core::marker::PhantomData))
-            }
+        TWI {
+            _twi: twi,
+            _sda: sda,
+            _scl: scl,
         }
     }
 
@@ -329,9 +267,7 @@ impl TWI {
         if addr > 127 {
             Err(TWIError::InvalidAddress)
         } else {
-            unsafe {
-                TWI_GLOBAL.address = addr << 1;
-            }
+            interrupt::free(|cs| TWI_GLOBAL.borrow(cs).borrow_mut().address = addr << 1);
             Ok(())
         }
     }
@@ -340,49 +276,67 @@ impl TWI {
     ///
     /// Waits for the transmission to finish before returning.
     pub fn write<T: ByteBundle + ?Sized>(&mut self, data: &T) -> Result<(), TWIError> {
-        // SAFETY: Assumes that there is only one instance of TWI.
-        unsafe {
+        interrupt::free(|cs| {
+            let mut global = TWI_GLOBAL.borrow(cs).borrow_mut();
+
             // In our limited implementation, the bus should be in the Ready state when we get here.
-            if TWI_GLOBAL.state() != TWIState::Ready {
+            if global.state != TWIState::Ready {
                 return Err(TWIError::NotReady);
             }
 
-            TWI_GLOBAL.buffer.set(data)?;
+            global.buffer.set(data)?;
 
-            TWI_GLOBAL.set_state(TWIState::Transmitting);
-            TWI_GLOBAL.set_error(TWSRStatus::NoInfo);
+            global.state = TWIState::Transmitting;
+            global.error = TWSRStatus::NoInfo;
 
-            // Enable the interrupt and Start signal.
+            Ok(())
+        })?;
+
+        // Enable the interrupt and Start signal.
+        unsafe {
             TWCR::set_value(TWCR::TWINT | TWCR::TWEA | TWCR::TWEN | TWCR::TWIE | TWCR::TWSTA);
+        }
 
-            // Wait for the write operation to complete.
-            // Because an interrupt will be changing the state, we need to do a volatile read
-            // otherwise the optimizer will helpfully decide that state can't possible change
-            // between each check as we don't change it here, and optimize us into an infinite
-            // loop.
-            // This is undesireable.
-            while TWI_GLOBAL.state() == TWIState::Transmitting {}
-
-            match TWI_GLOBAL.error() {
-                TWSRStatus::NoInfo => Ok(()),
-                TWSRStatus::MtDataNack => Err(TWIError::SendDataNACK),
-                TWSRStatus::MtSlaNack => Err(TWIError::SendAddressNACK),
-                _ => Err(TWIError::BusError),
-            }
+        // Wait for the write operation to complete.
+        // Each check is done in its own critical section, which also acts as a barrier, so the
+        // optimizer can't decide that the state can't possibly change between each check and
+        // optimize us into an infinite loop.
+        let is_transmitting =
+            || interrupt::free(|cs| TWI_GLOBAL.borrow(cs).borrow().state == TWIState::Transmitting);
+        while is_transmitting() {}
+
+        match interrupt::free(|cs| TWI_GLOBAL.borrow(cs).borrow().error) {
+            TWSRStatus::NoInfo => Ok(()),
+            TWSRStatus::MtDataNack => Err(TWIError::SendDataNACK),
+            TWSRStatus::MtSlaNack => Err(TWIError::SendAddressNACK),
+            _ => Err(TWIError::BusError),
         }
     }
 }
 
+/// The driver can only transmit, so only the write trait is implemented.
+#[cfg(feature = "embedded-hal-traits")]
+impl embedded_hal::blocking::i2c::Write for TWI {
+    type Error = TWIError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.set_address(address)?;
+        TWI::write(self, bytes)
+    }
+}
+
 impl Drop for TWI {
     fn drop(&mut self) {
         // SAFETY: Assumes only one TWI instance exists.
         unsafe {
-            HAS_INIT = false;
             // Disable TWI, turn off interrupt, turn off ACK.
             TWCR::clear_bits(TWCR::TWEN | TWCR::TWIE | TWCR::TWEA);
 
-            // Disable the internal pullups for the SDA and SCL pins.
-            PORTC::clear_bits(PORTC::PORTC4 | PORTC::PORTC5);
+            // Disable the internal pullups for the SDA and SCL pins, which share a port on
+            // all of the supported devices.
+            type PORT = <<SDA as PinId>::Port as Port>::PORT;
+            let pullups = (1 << SDA::BIT) | (1 << SCL::BIT);
+            PORT::set_raw_value(PORT::get_value() & !pullups);
         }
     }
 }
@@ -396,7 +350,7 @@ unsafe fn send_reply(ack: bool) {
     TWCR::set_value(bits);
 }
 
-unsafe fn stop() {
+unsafe fn stop(global: &mut TWIGlobalData) {
     TWCR::set_value(TWCR::TWEN | TWCR::TWEA | TWCR::TWINT | TWCR::TWSTO);
 
     // Wait for stop condition to be executed on bus.
@@ -405,18 +359,24 @@ unsafe fn stop() {
         continue;
     }
 
-    TWI_GLOBAL.set_state(TWIState::Ready);
+    global.state = TWIState::Ready;
 }
 
-unsafe fn release_bus() {
+unsafe fn release_bus(global: &mut TWIGlobalData) {
     TWCR::set_value(TWCR::TWEN | TWCR::TWEA | TWCR::TWINT);
 
-    TWI_GLOBAL.set_state(TWIState::Ready);
+    global.state = TWIState::Ready;
 }
 
+interrupt!(TWI, on_interrupt);
+
 /// TWI interrupt handler.
-#[no_mangle]
-pub unsafe extern "avr-interrupt" fn __vector_24() {
+fn on_interrupt() {
+    // Interrupts are already disabled in here, so this is just to get at the global data.
+    interrupt::free(|cs| unsafe { handle_status(&mut TWI_GLOBAL.borrow(cs).borrow_mut()) });
+}
+
+unsafe fn handle_status(global: &mut TWIGlobalData) {
     match TWSR::status() {
         /////////////////////
         // All Master
@@ -427,7 +387,7 @@ pub unsafe extern "avr-interrupt" fn __vector_24() {
         => {
             // Copy device address and R/W bit to output register and ACK.
             // In this case we know that we're always writing, which is a 0 in the R/W bit.
-            TWDR::set_raw_value(TWI_GLOBAL.address);
+            TWDR::set_raw_value(global.address);
             send_reply(true);
         },
 
@@ -439,28 +399,28 @@ pub unsafe extern "avr-interrupt" fn __vector_24() {
         | TWSRStatus::MtDataAck // Slave receiever ACKed data.
         => {
             // If there is data to send, send it, otherwise stop.
-            if let Some(byte) = TWI_GLOBAL.buffer.pop() {
+            if let Some(byte) = global.buffer.pop() {
                 TWDR::set_raw_value(byte);
                 send_reply(true);
             } else {
-                stop();
+                stop(global);
             }
         },
 
         // Address Sent, NACK received.
         TWSRStatus::MtSlaNack => {
-            TWI_GLOBAL.set_error(TWSRStatus::MtSlaNack);
-            stop();
+            global.error = TWSRStatus::MtSlaNack;
+            stop(global);
         },
         // Data Sent, NACK received.
         TWSRStatus::MtDataNack => {
-            TWI_GLOBAL.set_error(TWSRStatus::MtDataNack);
-            stop();
+            global.error = TWSRStatus::MtDataNack;
+            stop(global);
         },
         // Lost bus arbitration.
         TWSRStatus::MtArbLost => {
-            TWI_GLOBAL.set_error(TWSRStatus::MtArbLost);
-            release_bus();
+            global.error = TWSRStatus::MtArbLost;
+            release_bus(global);
         },
 
         /////////////////////
@@ -471,8 +431,8 @@ pub unsafe extern "avr-interrupt" fn __vector_24() {
         TWSRStatus::NoInfo => {},
         // Bus error, illegal stop/start
         TWSRStatus::BusError => {
-            TWI_GLOBAL.set_error(TWSRStatus::BusError);
-            stop();
+            global.error = TWSRStatus::BusError;
+            stop(global);
         }
 
         /////////////////////
@@ -485,3 +445,95 @@ pub unsafe extern "avr-interrupt" fn __vector_24() {
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hal::{register::Bit, sim};
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Simulates a device on the bus, which ACKs everything unless told to NACK its address.
+    /// Returns the bytes put on the bus, with `None` marking a stop condition.
+    pub(crate) fn simulate_bus(nack_address: bool) -> Rc<RefCell<Vec<Option<u8>>>> {
+        let bus = Rc::new(RefCell::new(Vec::new()));
+        let log = bus.clone();
+        let mut sent_address = false;
+
+        sim::on_write::<TWCR, _>(move |hw, val| {
+            let bit = |b: u8| val & (1 << b) != 0;
+
+            if bit(TWCR::TWSTO.bit_id()) {
+                // The hardware clears TWSTO once the stop condition has been sent.
+                log.borrow_mut().push(None);
+                hw.data[TWCR::ADDR as usize] = val & !(1 << TWCR::TWSTO.bit_id());
+                sent_address = false;
+                return;
+            }
+
+            hw.data[TWCR::ADDR as usize] = val;
+            if !bit(TWCR::TWINT.bit_id()) {
+                return;
+            }
+
+            let status = if bit(TWCR::TWSTA.bit_id()) {
+                TWSRStatus::Start
+            } else {
+                log.borrow_mut().push(Some(hw.get::<TWDR>()));
+
+                match (sent_address, nack_address) {
+                    (false, false) => TWSRStatus::MtSlaAck,
+                    (false, true) => TWSRStatus::MtSlaNack,
+                    (true, _) => TWSRStatus::MtDataAck,
+                }
+            };
+            sent_address = status != TWSRStatus::Start;
+
+            hw.set::<TWSR>((status as u8) << 3);
+            hw.raise(on_interrupt);
+        });
+
+        bus
+    }
+
+    /// Sets up the TWI to talk to the device at 0x3C, the display's address.
+    pub(crate) fn init() -> TWI {
+        let (sda, scl) = unsafe { (Pin::<SDA, _>::steal(), Pin::<SCL, _>::steal()) };
+        let mut twi = TWI::init(unsafe { TWI0::steal() }, sda, scl);
+        twi.set_address(0x3C).ok();
+        unsafe { interrupt::enable() };
+
+        twi
+    }
+
+    #[test]
+    fn bit_rate_for_each_cpu_frequency() {
+        // Too slow for 400KHz, so it runs as fast as it can.
+        assert_eq!(twi_bit_rate(1_000_000), 0);
+        assert_eq!(twi_bit_rate(8_000_000), 2);
+        assert_eq!(twi_bit_rate(16_000_000), 12);
+        assert_eq!(twi_bit_rate(20_000_000), 17);
+    }
+
+    #[test]
+    fn write_sends_address_then_data() {
+        let _hw = sim::reset();
+        let bus = simulate_bus(false);
+        let mut twi = init();
+
+        assert!(twi.write(&[1, 2, 3][..]).is_ok());
+        assert_eq!(*bus.borrow(), [Some(0x78), Some(1), Some(2), Some(3), None]);
+
+        // The bus should be free for the next write.
+        assert!(twi.write(&[4][..]).is_ok());
+    }
+
+    #[test]
+    fn write_reports_address_nack() {
+        let _hw = sim::reset();
+        let bus = simulate_bus(true);
+        let mut twi = init();
+
+        assert!(twi.write(&[1, 2, 3][..]) == Err(TWIError::SendAddressNACK));
+        assert_eq!(*bus.borrow(), [Some(0x78), None]);
+    }
+}
@@ -1,120 +1,89 @@
 //! A basic USART implementation to enable sending serial data for debugging.
 
 #![allow(dead_code)]
-use crate::hal::{register::Register, CPU_FREQ};
-use core::marker::PhantomData;
+use crate::hal::{
+    format_decimal,
+    register::{Field, Register},
+    CPU_FREQ,
+};
 
 pub mod registers {
-    reg! {
-        /// USART 0 I/O Data Register
-        UDR0: u8 {
-            addr: 0xC6,
-            write mask: 0xFF,
-        }
-    }
-
-    reg! {
-        /// USART 0 Control and Status Register A
-        UCSR0A: u8 {
-            addr: 0xC0,
-            write mask: 0b0100_0011,
-            bits: {
-                /// USART 0 Multi-processor Communication Mode
-                MPCM0 = 0, RW;
-                /// USART 0 Double Transmission Speed
-                U2X0 = 1, RW;
-                /// USART 0 Parity Error
-                UPE0 = 2, R;
-                /// USART 0 Data OverRun
-                DOR0 = 3, R;
-                /// USART 0 Frame Error
-                FE0 = 4, R;
-                /// USART 0 Data Register Empty
-                UDRE0 = 5, R;
-                /// USART 0 Transmit Complete
-                TXC0 = 6, RW;
-                /// USART 0 Receive Complete
-                RXC0 = 7, R;
-            }
-        }
-    }
-
-    reg! {
-        /// USART 0 Control and Status Register B
-        UCSR0B: u8 {
-            addr: 0xC1,
-            write mask: 0b1111_1101,
-            bits: {
-                /// USART 0 Transmit Data Bit 8
-                TXB80 = 0, RW;
-                /// USART 0 Receive Data Bit 8
-                RBX80 = 1, R;
-                /// USART 0 Character Size
-                UCSZ02 = 2, RW;
-                /// USART 0 Transmitter Enable
-                TXEN0 = 3, RW;
-                /// USART 0 Receiever Enable
-                RXEN0 = 4, RW;
-                /// USART 0 Data Register Empty Interrupt Enable
-                UDRIE0 = 5, RW;
-                /// USART 0 TX Complete Interrupt Enable
-                TXCIE0 = 6, RW;
-                /// USART 0 RX Complete Interrupt Enable
-                RXCIE0 = 7, RW;
-            }
-        }
-    }
+    #[cfg(not(feature = "atmega32u4"))]
+    pub use crate::hal::device::usart0::*;
+    #[cfg(feature = "atmega32u4")]
+    pub use crate::hal::device::usart1::*;
 
-    reg! {
-        /// USART 0 Control and Status Register C
-        UCSR0C: u8 {
-            addr: 0xC2,
-            write mask: 0xFF,
-            bits: {
-                /// USART 0 Clock Polarity
-                UCPOL0 = 0, RW;
-                /// USART 0 Character Size - Bit 0
-                UCSZ00 = 1, RW;
-                /// USART 0 Character Size - Bit 1
-                UCSZ01 = 2, RW;
-                /// USART 0 Stop Bit Select
-                USBS0 = 3, RW;
-                /// USART 0 Parity Mode - Bit 0
-                UPM00 = 4, RW;
-                /// USART 0 Parity Mode - Bit 1
-                UPM01 = 5, RW;
-                /// USART 0 Mode Select - Bit 0
-                UMSEL00 = 6, RW;
-                /// USART 0 Mode Select - Bit 1
-                UMSEL01 = 7, RW;
-            }
+    field_enum! {
+        /// The parity bit generated and checked by the USART.
+        ParityMode: u8 {
+            Disabled = 0,
+            Even = 2,
+            Odd = 3,
+            // 1 is reserved.
+            _ => Disabled,
         }
     }
 
-    reg! {
-        /// USART 0 Baud Rate Register
-        UBRR0: u16 {
-            addr: 0xC4,
-            write mask: 0x0FFF,
+    field_enum! {
+        /// The operating mode of the USART.
+        USARTMode: u8 {
+            Async = 0,
+            Sync = 1,
+            MasterSPI = 3,
+            // 2 is reserved.
+            _ => Async,
         }
     }
 }
 
-use registers::*;
+use registers::{ParityMode, USARTMode};
+
+// The 32U4 uses USART 1, as it has no USART 0. It's the one wired to the Pro Micro's TX and
+// RX pins, so it's a drop-in replacement. The registers and bits are renamed here so the
+// driver doesn't need to care which one it's using.
+#[cfg(not(feature = "atmega32u4"))]
+use registers::{
+    RXEN0 as RXEN, TXC0 as TXC, TXEN0 as TXEN, U2X0 as U2X, UBRR0 as UBRR, UCSR0A as UCSRA,
+    UCSR0B as UCSRB, UCSR0C as UCSRC, UCSZ00 as UCSZ0, UCSZ01 as UCSZ1, UDR0 as UDR, UDRE0 as UDRE,
+    UMSEL0 as UMSEL, UPM0 as UPM,
+};
+#[cfg(feature = "atmega32u4")]
+use registers::{
+    RXEN1 as RXEN, TXC1 as TXC, TXEN1 as TXEN, U2X1 as U2X, UBRR1 as UBRR, UCSR1A as UCSRA,
+    UCSR1B as UCSRB, UCSR1C as UCSRC, UCSZ10 as UCSZ0, UCSZ11 as UCSZ1, UDR1 as UDR, UDRE1 as UDRE,
+    UMSEL1 as UMSEL, UPM1 as UPM,
+};
 
 /// The baud rate we'll use for serial.
 ///
 /// 9600 is the default for PuTTY, so I've just used that value.
 const BAUD_RATE: u32 = 9600;
 /// The calculated value to put into the UBBR register to set the baud rate.
-const UBBR_VAL: u16 = ((CPU_FREQ / 8 / BAUD_RATE) - 1) as u16;
+///
+/// The USART runs at double speed, so it's clocked at an eighth of the CPU. Rounded to the
+/// nearest, as truncating can give a larger error.
+const fn ubbr_value(cpu_freq: u32) -> u16 {
+    ((cpu_freq + 4 * BAUD_RATE) / (8 * BAUD_RATE) - 1) as u16
+}
+const UBBR_VAL: u16 = ubbr_value(CPU_FREQ);
+/// The baud rate we actually get, as the CPU frequency won't divide exactly.
+const ACTUAL_BAUD_RATE: u32 = CPU_FREQ / (8 * (UBBR_VAL as u32 + 1));
 
-/// Tracks whether the USART has been initilised.
-static mut HAS_INIT: bool = false;
+// The baud rate is too low for UBRR's 12 bits.
+const_assert!((CPU_FREQ + 4 * BAUD_RATE) / (8 * BAUD_RATE) - 1 <= 0x0FFF);
+// The baud rate is more than 2% out, which the receiver may not cope with.
+const_assert!(ACTUAL_BAUD_RATE * 100 > BAUD_RATE * 98 && ACTUAL_BAUD_RATE * 100 < BAUD_RATE * 102);
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub enum USARTError {
-    InitError,
+/// Ownership token for USART 0, or USART 1 on the 32U4. Obtained from `Peripherals::take`.
+pub struct USART0 {
+    _p: (),
+}
+
+impl USART0 {
+    /// SAFETY: Only one token for the USART may exist at a time.
+    pub unsafe fn steal() -> Self {
+        Self { _p: () }
+    }
 }
 
 /// Provides an interface to the USART.
@@ -129,42 +98,59 @@ pub enum USARTError {
 /// * 1 stop bit
 /// * No parity bit
 ///
-/// Only one instance can live at a time.
-pub struct USART(PhantomData<()>);
+/// Consumes the USART's token, so only one instance can live at a time.
+pub struct USART {
+    _usart0: USART0,
+    /// Whether a byte has been sent since the last flush, so TXC will be set once it's gone.
+    transmitting: bool,
+}
 
 impl USART {
-    pub fn init() -> Result<USART, USARTError> {
+    pub fn init(usart0: USART0) -> USART {
         unsafe {
-            if HAS_INIT {
-                Err(USARTError::InitError)
-            } else {
-                // Set our baud rate.
-                UBRR0::set_raw_value(UBBR_VAL);
-
-                // Configure for:
-                // * 2x speed
-                // * 8-bit characters
-                // * 1 stop bit
-                // * No parity
-                // * Async mode,
-                // * Enable RX/TX
-                UCSR0A::set_value(UCSR0A::U2X0 | UCSR0A::MPCM0);
-                UCSR0B::set_value(UCSR0B::RXEN0 | UCSR0B::TXEN0);
-                UCSR0C::set_value(UCSR0C::UCSZ01 | UCSR0C::UCSZ00);
-
-                HAS_INIT = true;
-                Ok(USART(PhantomData))
-            }
+            // Set our baud rate.
+            UBRR::set_raw_value(UBBR_VAL);
+
+            // Configure for:
+            // * 2x speed
+            // * 8-bit characters
+            // * 1 stop bit
+            // * No parity
+            // * Async mode,
+            // * Enable RX/TX
+            UCSRA::set_value(U2X);
+            UCSRB::set_value(RXEN | TXEN);
+            UCSRC::set_value(
+                UMSEL.with(USARTMode::Async) | UPM.with(ParityMode::Disabled) | UCSZ1 | UCSZ0,
+            );
+        }
+
+        USART {
+            _usart0: usart0,
+            transmitting: false,
         }
     }
 
     pub fn send_byte(&mut self, data: u8) {
         unsafe {
             // Wait for the data register to become available.
-            while !UCSR0A::get_bit(UCSR0A::UDRE0) {}
+            while !UCSRA::get_bit(UDRE) {}
+        }
 
-            UDR0::set_raw_value(data);
+        self.load_byte(data);
+    }
+
+    /// Puts a byte in the empty data register.
+    ///
+    /// TXC is cleared by writing a 1 to it, so it's only set again once this byte has been
+    /// sent. It can't be set in the meantime by an earlier byte, as there's a byte waiting.
+    fn load_byte(&mut self, data: u8) {
+        unsafe {
+            UDR::set_raw_value(data);
+            UCSRA::set_bits(TXC);
         }
+
+        self.transmitting = true;
     }
 
     pub fn send<T: AsRef<[u8]>>(&mut self, data: T) {
@@ -173,13 +159,138 @@ impl USART {
         }
         inner(self, data.as_ref());
     }
+
+    /// Sends a number in decimal, without pulling in the formatting machinery.
+    pub fn send_decimal(&mut self, number: u16) {
+        let mut buf = [0; 10];
+        self.send(format_decimal(number as u32, &mut buf));
+    }
 }
 
+impl core::fmt::Write for USART {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.send(s);
+        Ok(())
+    }
+}
+
+/// `write` doesn't wait for the data register to empty, and `flush` waits until the last byte
+/// has been shifted out.
+#[cfg(feature = "embedded-hal-traits")]
+impl embedded_hal::serial::Write<u8> for USART {
+    type Error = core::convert::Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        if unsafe { !UCSRA::get_bit(UDRE) } {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.load_byte(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        // TXC is never set if nothing's been sent, so that would wait forever.
+        if !self.transmitting || unsafe { UCSRA::get_bit(TXC) } {
+            self.transmitting = false;
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-traits")]
+impl embedded_hal::blocking::serial::write::Default<u8> for USART {}
+
 impl Drop for USART {
     fn drop(&mut self) {
         unsafe {
-            UCSR0B::clear_bits(UCSR0B::TXEN0 | UCSR0B::RXEN0);
-            HAS_INIT = false;
+            UCSRB::clear_bits(TXEN | RXEN);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::sim;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn init_configures_9600_8n1() {
+        let _hw = sim::reset();
+        let _usart = USART::init(unsafe { USART0::steal() });
+
+        assert_eq!(sim::get::<UBRR>(), UBBR_VAL);
+        assert_eq!(sim::get::<UCSRA>(), 0b0000_0010);
+        assert_eq!(sim::get::<UCSRB>(), 0b0001_1000);
+        assert_eq!(sim::get::<UCSRC>(), 0b0000_0110);
+    }
+
+    #[test]
+    fn baud_rate_for_each_cpu_frequency() {
+        assert_eq!(ubbr_value(1_000_000), 12);
+        assert_eq!(ubbr_value(8_000_000), 103);
+        assert_eq!(ubbr_value(16_000_000), 207);
+        assert_eq!(ubbr_value(20_000_000), 259);
+    }
+
+    #[test]
+    fn send_writes_each_byte() {
+        let _hw = sim::reset();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let log = sent.clone();
+        sim::on_write::<UDR, _>(move |_, val| log.borrow_mut().push(val));
+        // The data register is always empty, as the bytes go nowhere.
+        sim::set::<UCSRA>(0b0010_0000);
+
+        let mut usart = USART::init(unsafe { USART0::steal() });
+        usart.send(b"hi\n");
+
+        assert_eq!(*sent.borrow(), b"hi\n");
+    }
+
+    #[test]
+    fn send_decimal_writes_digits() {
+        let _hw = sim::reset();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let log = sent.clone();
+        sim::on_write::<UDR, _>(move |_, val| log.borrow_mut().push(val));
+        sim::set::<UCSRA>(0b0010_0000);
+
+        let mut usart = USART::init(unsafe { USART0::steal() });
+        usart.send_decimal(0);
+        usart.send(b" ");
+        usart.send_decimal(65535);
+
+        assert_eq!(*sent.borrow(), b"0 65535");
+    }
+
+    #[cfg(feature = "embedded-hal-traits")]
+    #[test]
+    fn flush_waits_for_transmit_complete() {
+        use crate::hal::register::Bit;
+        use embedded_hal::serial::Write;
+
+        let _hw = sim::reset();
+        // Writing a 1 to TXC clears it, and the data register is always empty.
+        sim::on_write::<UCSRA, _>(|hw, val| {
+            let txc = 1 << TXC.bit_id();
+            let old = hw.get::<UCSRA>();
+            hw.set::<UCSRA>((val & !txc) | (old & txc & !val) | (1 << UDRE.bit_id()));
+        });
+
+        let mut usart = USART::init(unsafe { USART0::steal() });
+        assert!(usart.flush().is_ok());
+
+        // TXC is still set from an earlier byte.
+        sim::set::<UCSRA>(sim::get::<UCSRA>() | 1 << TXC.bit_id());
+        assert!(usart.write(b'a').is_ok());
+        assert!(matches!(usart.flush(), Err(nb::Error::WouldBlock)));
+
+        // The byte's been shifted out.
+        sim::set::<UCSRA>(sim::get::<UCSRA>() | 1 << TXC.bit_id());
+        assert!(usart.flush().is_ok());
+    }
+}
@@ -0,0 +1,305 @@
+//! The memory functions the compiler expects libc to provide.
+//!
+//! I'm not linking to libc, so these are needed by anything that copies, fills or compares a
+//! block of memory. The inner loops are in assembly, using the post-increment and
+//! pre-decrement forms of `LD` and `ST` so the pointers don't need reloading for every byte.
+//! Writing them in Rust risks LLVM recognising the loop and turning it back into a call to the
+//! function it's in.
+//!
+//! When testing on the host, the loops are replaced by plain Rust stand-ins, and the functions
+//! aren't exported so they can be compared with the real libc. The tests don't run the
+//! assembly, which is only built for the AVR, so they only cover the wrappers around the
+//! loops: the zero-length checks, memmove's choice of direction, and the return values.
+
+// C's int is 16 bits on the AVR.
+#[cfg(target_arch = "avr")]
+type CInt = i16;
+#[cfg(not(target_arch = "avr"))]
+type CInt = i32;
+
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn memcpy(dst: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    if n != 0 {
+        copy_forward(dst, src, n);
+    }
+
+    dst
+}
+
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn memmove(dst: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    if n == 0 || core::ptr::eq(dst, src) {
+        return dst;
+    }
+
+    // Copying forwards is only a problem if the start of the destination is inside the source,
+    // as the end of the source would be overwritten before it's read.
+    if (dst as usize).wrapping_sub(src as usize) >= n {
+        copy_forward(dst, src, n);
+    } else {
+        copy_backward(dst.add(n), src.add(n), n);
+    }
+
+    dst
+}
+
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn memset(s: *mut u8, c: CInt, n: usize) -> *mut u8 {
+    if n != 0 {
+        fill(s, c as u8, n);
+    }
+
+    s
+}
+
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn memcmp(a: *const u8, b: *const u8, n: usize) -> CInt {
+    if n == 0 {
+        return 0;
+    }
+
+    let (a, b) = first_difference(a, b, n);
+    a as CInt - b as CInt
+}
+
+// The loops below all run at least once, so `n` must not be zero, otherwise they'd go through
+// all 64KB.
+
+/// 8 cycles per byte.
+unsafe fn copy_forward(dst: *mut u8, src: *const u8, n: usize) {
+    #[cfg(test)]
+    for i in 0..n {
+        *dst.add(i) = *src.add(i);
+    }
+
+    #[cfg(not(test))]
+    {
+        let src = src as usize;
+        let dst = dst as usize;
+
+        llvm_asm! {
+            "1: ld r0, Z+
+            st X+, r0
+            sbiw r24, 1
+            brne 1b"
+            :
+            : "{r31}"(src >> 8),"{r30}"(src),"{r27}"(dst >> 8),"{r26}"(dst),"{r25}"(n >> 8),"{r24}"(n)
+            : "r0", "r24", "r25", "r26", "r27", "r30", "r31", "memory"
+            : "volatile"
+        }
+    }
+}
+
+/// Copies the `n` bytes before `src_end` to the `n` bytes before `dst_end`, starting from the
+/// end. 8 cycles per byte.
+unsafe fn copy_backward(dst_end: *mut u8, src_end: *const u8, n: usize) {
+    #[cfg(test)]
+    for i in 1..=n {
+        *dst_end.sub(i) = *src_end.sub(i);
+    }
+
+    #[cfg(not(test))]
+    {
+        let src = src_end as usize;
+        let dst = dst_end as usize;
+
+        llvm_asm! {
+            "1: ld r0, -Z
+            st -X, r0
+            sbiw r24, 1
+            brne 1b"
+            :
+            : "{r31}"(src >> 8),"{r30}"(src),"{r27}"(dst >> 8),"{r26}"(dst),"{r25}"(n >> 8),"{r24}"(n)
+            : "r0", "r24", "r25", "r26", "r27", "r30", "r31", "memory"
+            : "volatile"
+        }
+    }
+}
+
+/// 6 cycles per byte.
+unsafe fn fill(s: *mut u8, c: u8, n: usize) {
+    #[cfg(test)]
+    for i in 0..n {
+        *s.add(i) = c;
+    }
+
+    #[cfg(not(test))]
+    {
+        let s = s as usize;
+
+        llvm_asm! {
+            "1: st Z+, $0
+            sbiw r24, 1
+            brne 1b"
+            :
+            : "r"(c),"{r31}"(s >> 8),"{r30}"(s),"{r25}"(n >> 8),"{r24}"(n)
+            : "r24", "r25", "r30", "r31", "memory"
+            : "volatile"
+        }
+    }
+}
+
+/// Returns the first pair of bytes that differ, or the last pair if none do. 10 cycles per
+/// byte.
+unsafe fn first_difference(a: *const u8, b: *const u8, n: usize) -> (u8, u8) {
+    #[cfg(test)]
+    {
+        let mut i = 0;
+        while i < n - 1 && *a.add(i) == *b.add(i) {
+            i += 1;
+        }
+
+        (*a.add(i), *b.add(i))
+    }
+
+    #[cfg(not(test))]
+    {
+        let a = a as usize;
+        let b = b as usize;
+        let a_byte: u8;
+        let b_byte: u8;
+
+        llvm_asm! {
+            "1: ld $0, X+
+            ld $1, Z+
+            cp $0, $1
+            brne 2f
+            sbiw r24, 1
+            brne 1b
+            2:"
+            : "=&r"(a_byte),"=&r"(b_byte)
+            : "{r27}"(a >> 8),"{r26}"(a),"{r31}"(b >> 8),"{r30}"(b),"{r25}"(n >> 8),"{r24}"(n)
+            : "r24", "r25", "r26", "r27", "r30", "r31"
+            : "volatile"
+        }
+
+        (a_byte, b_byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! These run the Rust stand-ins for the loops, not the assembly, so a mistake in the
+    //! assembly won't show up here.
+
+    use super::*;
+    use crate::game::rng::Rng;
+
+    mod libc {
+        use super::CInt;
+
+        // Our versions aren't exported when testing, so these are the host's.
+        extern "C" {
+            pub fn memcpy(dst: *mut u8, src: *const u8, n: usize) -> *mut u8;
+            pub fn memmove(dst: *mut u8, src: *const u8, n: usize) -> *mut u8;
+            pub fn memset(s: *mut u8, c: CInt, n: usize) -> *mut u8;
+            pub fn memcmp(a: *const u8, b: *const u8, n: usize) -> CInt;
+        }
+    }
+
+    const LEN: usize = 64;
+
+    fn random_buf(rng: &mut Rng) -> [u8; LEN] {
+        let mut buf = [0; LEN];
+        buf.iter_mut().for_each(|b| *b = rng.next());
+        buf
+    }
+
+    /// A random offset and length that fit in the buffer.
+    fn random_span(rng: &mut Rng) -> (usize, usize) {
+        let start = rng.next() as usize % LEN;
+        let len = rng.next() as usize % (LEN - start + 1);
+        (start, len)
+    }
+
+    #[test]
+    fn wrappers_copy_and_fill_like_libc() {
+        let mut rng = Rng::new(0xACE1);
+
+        for _ in 0..500 {
+            let src = random_buf(&mut rng);
+            let mut ours = random_buf(&mut rng);
+            let mut theirs = ours;
+            let (dst_start, len) = random_span(&mut rng);
+            let src_start = rng.next() as usize % (LEN - len + 1);
+
+            unsafe {
+                let ret = memcpy(
+                    ours.as_mut_ptr().add(dst_start),
+                    src[src_start..].as_ptr(),
+                    len,
+                );
+                assert_eq!(ret, ours.as_mut_ptr().add(dst_start));
+                libc::memcpy(
+                    theirs.as_mut_ptr().add(dst_start),
+                    src[src_start..].as_ptr(),
+                    len,
+                );
+            }
+            assert_eq!(ours, theirs);
+
+            let c = rng.next() as CInt | 0x100;
+            let (start, len) = random_span(&mut rng);
+            unsafe {
+                let ret = memset(ours.as_mut_ptr().add(start), c, len);
+                assert_eq!(ret, ours.as_mut_ptr().add(start));
+                libc::memset(theirs.as_mut_ptr().add(start), c, len);
+            }
+            assert_eq!(ours, theirs);
+        }
+    }
+
+    #[test]
+    fn memmove_picks_the_right_direction_for_overlaps() {
+        let mut rng = Rng::new(0x1D2C);
+
+        for _ in 0..500 {
+            let mut ours = random_buf(&mut rng);
+            let mut theirs = ours;
+            let (src_start, len) = random_span(&mut rng);
+            let dst_start = rng.next() as usize % (LEN - len + 1);
+
+            unsafe {
+                let ret = memmove(
+                    ours.as_mut_ptr().add(dst_start),
+                    ours.as_ptr().add(src_start),
+                    len,
+                );
+                assert_eq!(ret, ours.as_mut_ptr().add(dst_start));
+                libc::memmove(
+                    theirs.as_mut_ptr().add(dst_start),
+                    theirs.as_ptr().add(src_start),
+                    len,
+                );
+            }
+            assert_eq!(
+                ours, theirs,
+                "src {}, dst {}, len {}",
+                src_start, dst_start, len
+            );
+        }
+    }
+
+    #[test]
+    fn memcmp_wrapper_orders_like_libc() {
+        let mut rng = Rng::new(0x5EED);
+
+        for _ in 0..500 {
+            let a = random_buf(&mut rng);
+            let mut b = a;
+            // Only change a few bytes, or nearly every comparison would end at the first one.
+            for _ in 0..rng.next() % 3 {
+                b[rng.next() as usize % LEN] = rng.next();
+            }
+            let (start, len) = random_span(&mut rng);
+
+            let (ours, theirs) = unsafe {
+                (
+                    memcmp(a[start..].as_ptr(), b[start..].as_ptr(), len),
+                    libc::memcmp(a[start..].as_ptr(), b[start..].as_ptr(), len),
+                )
+            };
+            assert_eq!(ours.signum(), theirs.signum());
+        }
+    }
+}
@@ -1,71 +1,104 @@
-#![no_std]
-#![no_main]
-#![feature(lang_items, llvm_asm, abi_avr_interrupt)]
-
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+// The tests only cover the HAL, the memory intrinsics, the crash log and the drawing code, so most
+// of the game is unused when testing.
+#![cfg_attr(test, allow(dead_code))]
+#![feature(lang_items, llvm_asm, abi_avr_interrupt, min_const_generics)]
+
+mod board;
+#[macro_use]
 mod hal;
+mod crash_log;
+#[cfg(not(test))]
+mod diagnostics;
+mod intrinsics;
+#[cfg(not(test))]
 mod no_std_stuff;
 mod peripherals;
 use peripherals::display::Display;
 mod game;
 use game::{rng::Rng, ContinueState, Game, Input};
+mod settings;
+use settings::Settings;
 
-use hal::{
-    clock::{self, ClockError},
-    twi,
-    usart::{self, USARTError},
-};
-
-use derive_more::From;
+use board::Board;
+use crash_log::Entry;
+use hal::{clock, eeprom::EEPROM, twi, usart, Error, Peripherals};
 
 /// This is the I2C bus address of the display I'm using.
 ///
 /// I have no idea what model it is, but it's running on a SSD1306 display driver.
 const DISPLAY_ADDR: u8 = 0x3C;
 
-/// A little helper function to format a u8 into decimal.
-///
-/// Used for the game over screen. Bytes are big-endian ordered.
-#[allow(dead_code)]
-fn format_u8(val: u8) -> [u8; 3] {
-    [
-        (val / 100) + b'0',
-        (val / 10) % 10 + b'0',
-        (val % 10) + b'0',
-    ]
-}
+fn run() -> Result<(), Error> {
+    // We'll be needing interrupts for the TWI module and the timer used in the clock.
+    // SAFETY: We're not in a critical section.
+    unsafe {
+        hal::interrupt::enable();
+    }
 
-#[derive(Copy, Clone, Eq, PartialEq, From)]
-enum ErrorKind {
-    TWI(twi::TWIError),
-    USART(usart::USARTError),
-    Clock(clock::ClockError),
-}
+    // This is the only place the peripherals are taken, so this can't fail.
+    let board = Board::new(Peripherals::take().unwrap());
 
-fn run() -> Result<(), ErrorKind> {
-    // We'll be needing interrupts for the TWI module and the timer used in the clock.
-    hal::enable_interrupts();
+    let mut eeprom = board.eeprom;
+    crash_log::record_reset_cause(&mut eeprom);
 
-    let clock = clock::Clock::init()?;
+    let clock = clock::Clock::init(board.tc0);
 
-    let mut _usart = usart::USART::init()?;
+    let mut usart = usart::USART::init(board.usart0);
 
-    let mut twi = twi::TWI::init()?;
+    let mut twi = twi::TWI::init(board.twi, board.sda, board.scl);
     twi.set_address(DISPLAY_ADDR)?;
     let mut display = Display::init(&mut twi)?;
+    Settings::load(&eeprom).apply(&mut display, &mut twi)?;
+
+    let left = board.left.into_pull_up_input();
+    // Give the pull-up time to bring the pin high before reading it.
+    hal::delay_micros(10);
 
-    let mut input = Input::init();
+    // Holding left at power-on shows the crash log before the title screen.
+    let show_crash_log = left.is_low();
+    if show_crash_log {
+        crash_log::dump(&eeprom, &mut usart);
+        crash_log::show(&eeprom, &mut display, &mut twi)?;
+
+        // The button's still held, so wait for it to be let go, otherwise the first update
+        // would see it as a press.
+        while left.is_low() {}
+    }
+
+    let mut input = Input::init(
+        left,
+        board.right.into_pull_up_input(),
+        board.down.into_pull_up_input(),
+        board.up.into_pull_up_input(),
+    );
     let mut game = Game::new();
 
+    if show_crash_log {
+        loop {
+            let now = clock.now();
+            if input.update(now) {
+                break;
+            }
+        }
+    }
+
     display.display_splash(&mut twi, Game::title_screen())?;
 
     // We'll be using the time spent on the title screen as the seed for the RNG.
     // Given the precision of the clock, that gives 2^16 game states.
     let now = clock.now();
-    // Wait for player to press button
+    // Wait for player to press button. Up opens the settings instead.
     loop {
         let now = clock.now();
         if input.update(now) {
-            break;
+            if !input.up() {
+                break;
+            }
+
+            settings::menu(&mut eeprom, &mut display, &mut twi, &mut input, &clock)?;
+            display.display_splash(&mut twi, Game::title_screen())?;
         }
     }
     let seed = clock.now().elapsed(now);
@@ -101,15 +134,9 @@ fn run() -> Result<(), ErrorKind> {
         }
 
         // Game over state.
+        hal::ram::ram_report(&mut usart);
         display.display_splash(&mut twi, Game::game_over_screen())?;
-        let level = game.level();
-        let numbers = format_u8(level);
-
-        display.set_draw_coords(&mut twi, 10, 5)?;
-        for digit in numbers.iter().filter(|d| **d != b'0') {
-            let tile = Game::get_digit_tile(*digit);
-            display.draw_tile(&mut twi, &tile)?;
-        }
+        display.draw_number(&mut twi, 80, 5, game.level() as u32, false)?;
 
         // Wait for player to press button
         loop {
@@ -123,18 +150,20 @@ fn run() -> Result<(), ErrorKind> {
     }
 }
 
-#[no_mangle]
+#[cfg_attr(not(test), no_mangle)]
 pub extern "C" fn main() {
-    match run() {
-        Err(ErrorKind::TWI(twi::TWIError::BufferLenError)) => hal::blink_error_code(1),
-        Err(ErrorKind::TWI(twi::TWIError::SendAddressNACK)) => hal::blink_error_code(2),
-        Err(ErrorKind::TWI(twi::TWIError::SendDataNACK)) => hal::blink_error_code(3),
-        Err(ErrorKind::TWI(twi::TWIError::NotReady)) => hal::blink_error_code(4),
-        Err(ErrorKind::TWI(twi::TWIError::BusError)) => hal::blink_error_code(5),
-        Err(ErrorKind::TWI(twi::TWIError::InitError)) => hal::blink_error_code(6),
-        Err(ErrorKind::TWI(twi::TWIError::InvalidAddress)) => hal::blink_error_code(7),
-        Err(ErrorKind::USART(USARTError::InitError)) => hal::blink_error_code(8),
-        Err(ErrorKind::Clock(ClockError::InitError)) => hal::blink_error_code(9),
-        Ok(()) => {}
+    // SAFETY: Interrupts are disabled until `run` enables them.
+    unsafe {
+        hal::ram::paint_stack();
     }
+
+    let error = match run() {
+        Err(error) => error,
+        Ok(()) => return,
+    };
+
+    // SAFETY: `run` has returned, so nothing else has the EEPROM.
+    let mut eeprom = unsafe { EEPROM::steal() };
+    crash_log::record(&mut eeprom, Entry::Error(error.code()));
+    hal::blink_error_code(error);
 }
@@ -1,24 +1,23 @@
 use crate::hal::{
     clock::Instant,
-    ports::{PinMode, Port},
+    ports::{Input, Pin, PinId, PinSet, PullUp},
 };
 
 const DEBOUNCE_TIME: u16 = 2;
 
 /// This type implements some software debouncing of the button input, as well
 /// as keeps track of whether the state was changed in the previous update.
-pub struct Button<PinPort: Port> {
+pub struct Button<ID: PinId> {
     is_pressed: bool,
     had_state_change: bool,
     last_press_time: Instant,
-    pin: PinPort::ValidPins,
+    pin: Pin<ID, Input<PullUp>>,
 }
 
-impl<PinPort: Port> Button<PinPort> {
-    /// Initialises the port to Input with the internal pullup enabled.
-    pub fn new(pin: PinPort::ValidPins) -> Self {
-        PinPort::set_pin_mode(pin, PinMode::InputPullup);
-
+impl<ID: PinId> Button<ID> {
+    /// Takes a pin configured as an input with the internal pullup enabled, as the button
+    /// is expected to ground the pin when pressed.
+    pub fn new(pin: Pin<ID, Input<PullUp>>) -> Self {
         Self {
             is_pressed: false,
             had_state_change: false,
@@ -27,13 +26,14 @@ impl<PinPort: Port> Button<PinPort> {
         }
     }
 
-    /// Updates the current state of the pin, and returns whether or not the current state of the button changed.
-    pub fn update(&mut self, cur_time: Instant) -> bool {
+    /// Updates the current state of the pin from a snapshot of its port, and returns whether or not
+    /// the current state of the button changed.
+    pub fn update(&mut self, pins: PinSet<ID::Port>, cur_time: Instant) -> bool {
         // The state here is the state of the pin, not whether the button is pressed.
         // Because we're using the pullup resister, pressing the button will ground the
         // pin, causing it to read *low* when pressed.
         // I'm inverting here because I find it easier to reason about if true = pressed.
-        let cur_pressed = !PinPort::get_pin_state(self.pin);
+        let cur_pressed = pins.is_low(&self.pin);
 
         self.had_state_change = false;
 
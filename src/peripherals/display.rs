@@ -2,26 +2,103 @@ use crate::hal::{
     progmem::{ByteBundle, PGMSlice},
     twi,
 };
-use core::marker::PhantomData;
 
 pub const WIDTH: u8 = 128;
 pub const HEIGHT: u8 = 64;
 
-const SSD1306_COMMAND: u8 = 0x00;
-const SSD1306_DATA: u8 = 0x40;
+/// The size of the screen in 8x8 cells.
+pub const COLUMNS: u8 = WIDTH / 8;
+pub const ROWS: u8 = HEIGHT / 8;
+
+pub(super) const SSD1306_COMMAND: u8 = 0x00;
+pub(super) const SSD1306_DATA: u8 = 0x40;
 
 const SSD1306_MEMORYMODE: u8 = 0x20;
-const SSD1306_COLUMNADDR: u8 = 0x21;
-const SSD1306_PAGEADDR: u8 = 0x22;
+pub(super) const SSD1306_COLUMNADDR: u8 = 0x21;
+pub(super) const SSD1306_PAGEADDR: u8 = 0x22;
+const SSD1306_SETSTARTLINE: u8 = 0x40;
+const SSD1306_SETCONTRAST: u8 = 0x81;
 const SSD1306_CHARGEPUMP: u8 = 0x8D;
 const SSD1306_SEGREMAP: u8 = 0xA0;
+const SSD1306_DISPLAYALLON_RESUME: u8 = 0xA4;
+const SSD1306_DISPLAYALLON: u8 = 0xA5;
+const SSD1306_NORMALDISPLAY: u8 = 0xA6;
+const SSD1306_INVERTDISPLAY: u8 = 0xA7;
+const SSD1306_SETMULTIPLEX: u8 = 0xA8;
 const SSD1306_DISPLAYOFF: u8 = 0xAE;
 const SSD1306_DISPLAYON: u8 = 0xAF;
+const SSD1306_COMSCANINC: u8 = 0xC0;
 const SSD1306_COMSCANDEC: u8 = 0xC8;
 const SSD1306_SETPRECHARGE: u8 = 0xD9;
 const SSD1306_SETVCOMDETECT: u8 = 0xDB;
 
-pub struct Display(PhantomData<()>);
+/// The ID of a cell whose contents aren't known, so it'll always be redrawn.
+const UNKNOWN_TILE: u8 = 0xF;
+
+/// Keeps track of which tile is on each cell of the screen, so that cells that haven't changed
+/// don't need to be sent again.
+///
+/// RAM is tight, so each cell's tile ID only gets a nibble.
+struct TileShadow {
+    cells: [u8; COLUMNS as usize * ROWS as usize / 2],
+}
+
+impl TileShadow {
+    const fn unknown() -> TileShadow {
+        TileShadow {
+            cells: [0xFF; COLUMNS as usize * ROWS as usize / 2],
+        }
+    }
+
+    fn get(&self, x: u8, y: u8) -> u8 {
+        let idx = y as usize * COLUMNS as usize + x as usize;
+        let cell = self.cells[idx / 2];
+        if idx % 2 == 0 {
+            cell & 0x0F
+        } else {
+            cell >> 4
+        }
+    }
+
+    fn set(&mut self, x: u8, y: u8, id: u8) {
+        let idx = y as usize * COLUMNS as usize + x as usize;
+        let cell = &mut self.cells[idx / 2];
+        if idx % 2 == 0 {
+            *cell = (*cell & 0xF0) | id;
+        } else {
+            *cell = (*cell & 0x0F) | (id << 4);
+        }
+    }
+
+    /// Moves the rows up, or down if `rows` is negative, to follow the screen being scrolled.
+    /// The rows that come into view are unknown.
+    fn shift_rows(&mut self, rows: i8) {
+        const ROW_LEN: usize = COLUMNS as usize / 2;
+        let len = self.cells.len();
+        let shift = ((rows as i16).unsigned_abs() as usize * ROW_LEN).min(len);
+
+        let exposed = if rows > 0 {
+            self.cells.copy_within(shift.., 0);
+            len - shift..len
+        } else {
+            self.cells.copy_within(..len - shift, shift);
+            0..shift
+        };
+        for cell in &mut self.cells[exposed] {
+            *cell = 0xFF;
+        }
+    }
+}
+
+pub struct Display {
+    shadow: TileShadow,
+    /// The cell the next tile will be drawn to, if known.
+    cursor: Option<(u8, u8)>,
+    /// The column the display goes back to when it moves to the next page.
+    start_column: u8,
+    /// The page of the display's memory that's shown on the top row, moved by `scroll_rows`.
+    start_page: u8,
+}
 
 impl Display {
     pub fn init(twi: &mut twi::TWI) -> Result<Display, twi::TWIError> {
@@ -44,10 +121,176 @@ impl Display {
         ];
         twi.write(init.as_ref())?;
 
-        Ok(Self(PhantomData))
+        Ok(Self {
+            shadow: TileShadow::unknown(),
+            cursor: None,
+            start_column: 0,
+            start_page: 0,
+        })
     }
 
-    pub fn clear_display(&mut self, twi: &mut twi::TWI) -> Result<(), twi::TWIError> {
+    /// Sets the brightness of the lit pixels. It's 127 after the display is reset.
+    pub fn set_contrast(&mut self, twi: &mut twi::TWI, contrast: u8) -> Result<(), twi::TWIError> {
+        twi.write([SSD1306_COMMAND, SSD1306_SETCONTRAST, contrast].as_ref())
+    }
+
+    /// Swaps lit and unlit pixels, without changing what's been drawn.
+    pub fn set_inverted(
+        &mut self,
+        twi: &mut twi::TWI,
+        inverted: bool,
+    ) -> Result<(), twi::TWIError> {
+        let command = if inverted {
+            SSD1306_INVERTDISPLAY
+        } else {
+            SSD1306_NORMALDISPLAY
+        };
+        twi.write([SSD1306_COMMAND, command].as_ref())
+    }
+
+    /// Mirrors the screen left to right.
+    ///
+    /// The display only applies this to data sent afterwards, so what's already on the screen
+    /// stays where it is, and the screen needs to be redrawn.
+    pub fn set_flip_horizontal(
+        &mut self,
+        twi: &mut twi::TWI,
+        flip: bool,
+    ) -> Result<(), twi::TWIError> {
+        // Column 0 is normally mapped to the last segment, as that's the left of this display.
+        let remap = if flip { 0x0 } else { 0x1 };
+        self.invalidate();
+        twi.write([SSD1306_COMMAND, SSD1306_SEGREMAP | remap].as_ref())
+    }
+
+    /// Flips the screen upside down. Unlike the horizontal flip, this applies to what's already
+    /// on the screen.
+    pub fn set_flip_vertical(
+        &mut self,
+        twi: &mut twi::TWI,
+        flip: bool,
+    ) -> Result<(), twi::TWIError> {
+        let command = if flip {
+            SSD1306_COMSCANINC
+        } else {
+            SSD1306_COMSCANDEC
+        };
+        twi.write([SSD1306_COMMAND, command].as_ref())
+    }
+
+    /// Turns the display on or off. It keeps what's been drawn while off, and can still be drawn
+    /// to, but uses much less power.
+    pub fn set_display_on(&mut self, twi: &mut twi::TWI, on: bool) -> Result<(), twi::TWIError> {
+        let command = if on {
+            SSD1306_DISPLAYON
+        } else {
+            SSD1306_DISPLAYOFF
+        };
+        twi.write([SSD1306_COMMAND, command].as_ref())
+    }
+
+    /// Lights every pixel regardless of what's been drawn, for finding dead pixels. Turning it
+    /// off shows what was drawn again.
+    pub fn set_entire_display_on(
+        &mut self,
+        twi: &mut twi::TWI,
+        on: bool,
+    ) -> Result<(), twi::TWIError> {
+        let command = if on {
+            SSD1306_DISPLAYALLON
+        } else {
+            SSD1306_DISPLAYALLON_RESUME
+        };
+        twi.write([SSD1306_COMMAND, command].as_ref())
+    }
+
+    /// Sets how many rows of pixels are driven, from the top, which must be from 16 to 64.
+    ///
+    /// It's 64 after a reset. Panels with fewer rows need it set to their height, and setting it
+    /// lower on this one blanks the rows below.
+    pub fn set_multiplex_ratio(
+        &mut self,
+        twi: &mut twi::TWI,
+        rows: u8,
+    ) -> Result<(), twi::TWIError> {
+        assert!((16..=HEIGHT).contains(&rows), "Invalid multiplex ratio");
+        twi.write([SSD1306_COMMAND, SSD1306_SETMULTIPLEX, rows - 1].as_ref())
+    }
+
+    /// Scrolls the screen up by whole rows, or down if `rows` is negative, without sending it
+    /// again.
+    ///
+    /// The display's memory is shown starting from a different page, so the rows that scroll
+    /// off one edge wrap around to the other, and are forgotten so they'll be redrawn. Rows are
+    /// still numbered from the top of the screen.
+    pub fn scroll_rows(&mut self, twi: &mut twi::TWI, rows: i8) -> Result<(), twi::TWIError> {
+        self.start_page = (self.start_page as i16 + rows as i16).rem_euclid(ROWS as i16) as u8;
+        self.shadow.shift_rows(rows);
+        self.cursor = None;
+        self.set_scroll_offset(twi, 0)
+    }
+
+    /// Moves the screen up by up to 7 pixels past the rows scrolled by `scroll_rows`, or down if
+    /// `pixels` is negative, so a scroll can be animated a pixel at a time. It doesn't change
+    /// where anything is drawn.
+    ///
+    /// The pixels that move off one edge of the screen wrap around to the other, as they share
+    /// the display's memory with the row that will come into view.
+    pub fn set_scroll_offset(
+        &mut self,
+        twi: &mut twi::TWI,
+        pixels: i8,
+    ) -> Result<(), twi::TWIError> {
+        debug_assert!(-8 < pixels && pixels < 8);
+        let line = (self.start_page as i16 * 8 + pixels as i16).rem_euclid(HEIGHT as i16) as u8;
+        twi.write([SSD1306_COMMAND, SSD1306_SETSTARTLINE | line].as_ref())
+    }
+
+    /// Returns the page of the display's memory that's shown on a row of the screen.
+    pub(super) fn memory_page(&self, row: u8) -> u8 {
+        (row + self.start_page) % ROWS
+    }
+
+    /// Forgets what's on the screen, so the next `draw_tile_at` to each cell will send its tile.
+    pub fn invalidate(&mut self) {
+        self.shadow = TileShadow::unknown();
+        self.cursor = None;
+    }
+
+    /// Forgets what's on the cells overlapping an area, given in pixel columns and pages, as
+    /// it's about to be drawn over.
+    pub(super) fn forget_area(&mut self, x: u8, page: u8, width: u8, pages: u8) {
+        debug_assert!(width > 0);
+        for y in page..page + pages {
+            for x in x / 8..=(x + width - 1) / 8 {
+                self.shadow.set(x, y, UNKNOWN_TILE);
+            }
+        }
+        self.cursor = None;
+    }
+
+    /// Sends pixel data to the current draw area, split up to fit the TWI buffer.
+    pub(super) fn send_data(
+        &mut self,
+        twi: &mut twi::TWI,
+        data: &[u8],
+    ) -> Result<(), twi::TWIError> {
+        // Each write needs the data flag in front, which leaves one less byte for the data.
+        for chunk in data.chunks(twi::BUFFER_LEN - 1) {
+            let mut buf = [SSD1306_DATA; twi::BUFFER_LEN];
+            buf[1..=chunk.len()].copy_from_slice(chunk);
+            twi.write(&buf[..=chunk.len()])?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the draw area to the whole screen, ready for it all to be sent.
+    pub(super) fn select_whole_screen(&mut self, twi: &mut twi::TWI) -> Result<(), twi::TWIError> {
+        // Undo any scrolling, so the data starts at the top of the screen.
+        self.start_page = 0;
+        self.set_scroll_offset(twi, 0)?;
+
         let commands = [
             SSD1306_COMMAND,
             SSD1306_PAGEADDR,
@@ -57,7 +300,12 @@ impl Display {
             0x00, // Column start address
             WIDTH - 1,
         ];
-        twi.write(commands.as_ref())?;
+        self.invalidate();
+        twi.write(commands.as_ref())
+    }
+
+    pub fn clear_display(&mut self, twi: &mut twi::TWI) -> Result<(), twi::TWIError> {
+        self.select_whole_screen(twi)?;
 
         let mut buf = [0x00; twi::BUFFER_LEN];
         buf[0] = SSD1306_DATA;
@@ -75,16 +323,7 @@ impl Display {
         twi: &mut twi::TWI,
         splash: PGMSlice,
     ) -> Result<(), twi::TWIError> {
-        let commands = [
-            SSD1306_COMMAND,
-            SSD1306_PAGEADDR,
-            0, // Page start address
-            7, // Page end
-            SSD1306_COLUMNADDR,
-            0,   // Column start address
-            127, // Column end address
-        ];
-        twi.write(commands.as_ref())?;
+        self.select_whole_screen(twi)?;
 
         for chunk in splash.chunks(twi::BUFFER_LEN) {
             twi.write(&chunk)?;
@@ -102,20 +341,266 @@ impl Display {
         let commands = [
             SSD1306_COMMAND,
             SSD1306_PAGEADDR,
-            y, // Page start address
-            7, // Page end
+            self.memory_page(y), // Page start address
+            ROWS - 1,            // Page end
             SSD1306_COLUMNADDR,
             x * 8,     // Column start address
             WIDTH - 1, // Column end address
         ];
-        twi.write(commands.as_ref())
+        self.cursor = None;
+        twi.write(commands.as_ref())?;
+        self.cursor = Some((x, y));
+        self.start_column = x;
+
+        Ok(())
+    }
+
+    /// Draws a tile at the given cell, unless the tile with the same ID is already there.
+    ///
+    /// The ID must be less than 15, and be unique to the tile's graphic.
+    pub fn draw_tile_at<T: ByteBundle>(
+        &mut self,
+        twi: &mut twi::TWI,
+        x: u8,
+        y: u8,
+        id: u8,
+        tile: &T,
+    ) -> Result<(), twi::TWIError> {
+        debug_assert!(id < UNKNOWN_TILE);
+        if self.shadow.get(x, y) == id {
+            return Ok(());
+        }
+
+        // Runs of changed cells are common, and the display moves to the next cell by itself.
+        if self.cursor != Some((x, y)) {
+            self.set_draw_coords(twi, x, y)?;
+        }
+
+        // If sending fails we don't know what made it to the display.
+        self.shadow.set(x, y, UNKNOWN_TILE);
+        self.send_tile(twi, tile)?;
+        self.shadow.set(x, y, id);
+
+        Ok(())
     }
 
-    pub fn draw_tile<T: ByteBundle>(
+    fn send_tile<T: ByteBundle>(
         &mut self,
         twi: &mut twi::TWI,
         tile: &T,
     ) -> Result<(), twi::TWIError> {
-        twi.write(tile)
+        let cursor = self.cursor.take();
+        twi.write(tile)?;
+
+        // The display wraps back to the starting page after the last one, which we don't keep,
+        // so the cursor's lost at the end of the last page. That's the bottom right, unless the
+        // screen's been scrolled.
+        let start_column = self.start_column;
+        self.cursor = cursor.and_then(|(x, y)| match (x + 1, y) {
+            (COLUMNS, y) if y + 1 < ROWS && self.memory_page(y) + 1 < ROWS => {
+                Some((start_column, y + 1))
+            }
+            (COLUMNS, _) => None,
+            next => Some(next),
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hal::{
+        sim,
+        twi::tests::{init as init_twi, simulate_bus},
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    progmem! {
+        static TILE: [u8; 9] = [SSD1306_DATA, 1, 2, 3, 4, 5, 6, 7, 8];
+    }
+
+    /// Takes the writes logged by the simulated bus, without their address byte.
+    pub(crate) fn take_writes(bus: &Rc<RefCell<Vec<Option<u8>>>>) -> Vec<Vec<u8>> {
+        let bytes: Vec<_> = bus.borrow_mut().drain(..).collect();
+        bytes
+            .split(Option::is_none)
+            .filter(|write| !write.is_empty())
+            .map(|write| write[1..].iter().map(|b| b.unwrap()).collect())
+            .collect()
+    }
+
+    fn draw_coords(x: u8, page: u8) -> Vec<u8> {
+        let commands = [
+            SSD1306_COMMAND,
+            SSD1306_PAGEADDR,
+            page,
+            ROWS - 1,
+            SSD1306_COLUMNADDR,
+            x * 8,
+            WIDTH - 1,
+        ];
+        commands.to_vec()
+    }
+
+    fn draw(display: &mut Display, twi: &mut twi::TWI, x: u8, y: u8, id: u8) {
+        assert!(display
+            .draw_tile_at(twi, x, y, id, &TILE.as_slice())
+            .is_ok());
+    }
+
+    #[test]
+    fn shadow_cells_are_independent() {
+        let mut shadow = TileShadow::unknown();
+        assert_eq!(shadow.get(3, 4), UNKNOWN_TILE);
+
+        // Neighbouring cells share a byte.
+        shadow.set(2, 4, 5);
+        shadow.set(3, 4, 9);
+        shadow.set(0, 5, 1);
+        assert_eq!(shadow.get(2, 4), 5);
+        assert_eq!(shadow.get(3, 4), 9);
+        assert_eq!(shadow.get(1, 5), UNKNOWN_TILE);
+
+        shadow.set(2, 4, 0);
+        assert_eq!(shadow.get(2, 4), 0);
+        assert_eq!(shadow.get(3, 4), 9);
+    }
+
+    #[test]
+    fn unchanged_tiles_are_not_resent() {
+        let _hw = sim::reset();
+        let bus = simulate_bus(false);
+        let mut twi = init_twi();
+        let mut display = Display::init(&mut twi).ok().unwrap();
+        let tile_data: Vec<u8> = TILE.as_slice().iter().collect();
+        take_writes(&bus);
+
+        draw(&mut display, &mut twi, 0, 0, 1);
+        assert_eq!(take_writes(&bus), [draw_coords(0, 0), tile_data.clone()]);
+
+        draw(&mut display, &mut twi, 0, 0, 1);
+        assert!(take_writes(&bus).is_empty());
+
+        // The display has moved on to the next cell by itself.
+        draw(&mut display, &mut twi, 1, 0, 2);
+        assert_eq!(take_writes(&bus), [tile_data.clone()]);
+
+        // A different tile on the same cell is sent.
+        draw(&mut display, &mut twi, 0, 0, 2);
+        assert_eq!(take_writes(&bus), [draw_coords(0, 0), tile_data.clone()]);
+
+        display.invalidate();
+        draw(&mut display, &mut twi, 1, 0, 2);
+        assert_eq!(take_writes(&bus), [draw_coords(1, 0), tile_data]);
+    }
+
+    #[test]
+    fn cursor_wraps_to_the_start_column() {
+        let _hw = sim::reset();
+        let bus = simulate_bus(false);
+        let mut twi = init_twi();
+        let mut display = Display::init(&mut twi).ok().unwrap();
+        let tile_data: Vec<u8> = TILE.as_slice().iter().collect();
+
+        draw(&mut display, &mut twi, COLUMNS - 2, 2, 1);
+        draw(&mut display, &mut twi, COLUMNS - 1, 2, 1);
+        take_writes(&bus);
+
+        // The next page starts back at the column the draw area was set to.
+        draw(&mut display, &mut twi, COLUMNS - 2, 3, 1);
+        assert_eq!(take_writes(&bus), [tile_data.clone()]);
+
+        // The display goes back to the first page after the last, so the cursor's lost.
+        draw(&mut display, &mut twi, COLUMNS - 1, ROWS - 1, 1);
+        draw(&mut display, &mut twi, COLUMNS - 2, 0, 1);
+        assert_eq!(
+            take_writes(&bus),
+            [
+                draw_coords(COLUMNS - 1, ROWS - 1),
+                tile_data.clone(),
+                draw_coords(COLUMNS - 2, 0),
+                tile_data
+            ]
+        );
+    }
+
+    #[test]
+    fn scrolling_a_row_only_resends_the_exposed_row() {
+        let _hw = sim::reset();
+        let bus = simulate_bus(false);
+        let mut twi = init_twi();
+        let mut display = Display::init(&mut twi).ok().unwrap();
+        let tile_data: Vec<u8> = TILE.as_slice().iter().collect();
+        for y in 0..ROWS {
+            for x in 0..COLUMNS {
+                draw(&mut display, &mut twi, x, y, y);
+            }
+        }
+        take_writes(&bus);
+
+        assert!(display.scroll_rows(&mut twi, 1).is_ok());
+        assert_eq!(take_writes(&bus), [[0x00, 0x40 | 8]]);
+
+        // Redraw the screen as the game would, with every row moved up one.
+        for y in 0..ROWS {
+            for x in 0..COLUMNS {
+                draw(&mut display, &mut twi, x, y, y + 1);
+            }
+        }
+        let writes = take_writes(&bus);
+        // The bottom row is in the memory page that was at the top.
+        assert_eq!(writes[0], draw_coords(0, 0));
+        assert_eq!(writes.len(), 1 + COLUMNS as usize);
+        assert!(writes[1..].iter().all(|write| *write == tile_data));
+    }
+
+    #[test]
+    fn scroll_offsets_wrap_around_the_display() {
+        let _hw = sim::reset();
+        let bus = simulate_bus(false);
+        let mut twi = init_twi();
+        let mut display = Display::init(&mut twi).ok().unwrap();
+        take_writes(&bus);
+
+        assert!(display.set_scroll_offset(&mut twi, 7).is_ok());
+        assert!(display.set_scroll_offset(&mut twi, -1).is_ok());
+        assert!(display.scroll_rows(&mut twi, -1).is_ok());
+        assert!(display.set_scroll_offset(&mut twi, 3).is_ok());
+        assert_eq!(
+            take_writes(&bus),
+            [
+                [0x00, 0x40 | 7],
+                [0x00, 0x40 | 63],
+                [0x00, 0x40 | 56],
+                [0x00, 0x40 | 59]
+            ]
+        );
+    }
+
+    #[test]
+    fn scrolling_moves_the_shadow_rows() {
+        let mut shadow = TileShadow::unknown();
+        for y in 0..ROWS {
+            shadow.set(0, y, y);
+            shadow.set(COLUMNS - 1, y, y);
+        }
+
+        shadow.shift_rows(2);
+        assert_eq!(shadow.get(0, 0), 2);
+        assert_eq!(shadow.get(COLUMNS - 1, 5), 7);
+        assert_eq!(shadow.get(0, 6), UNKNOWN_TILE);
+        assert_eq!(shadow.get(COLUMNS - 1, 7), UNKNOWN_TILE);
+
+        shadow.shift_rows(-1);
+        assert_eq!(shadow.get(0, 0), UNKNOWN_TILE);
+        assert_eq!(shadow.get(0, 1), 2);
+        assert_eq!(shadow.get(COLUMNS - 1, 6), 7);
+        assert_eq!(shadow.get(0, 7), UNKNOWN_TILE);
+
+        // Scrolling further than the screen loses everything.
+        shadow.shift_rows(-20);
+        assert!((0..ROWS).all(|y| shadow.get(0, y) == UNKNOWN_TILE));
     }
 }
@@ -0,0 +1,108 @@
+// Generated by the build.rs file from build/font.rs during compilation
+
+/// The character of the first glyph.
+pub const FIRST_CHAR: u8 = 32;
+/// The character of the last glyph.
+pub const LAST_CHAR: u8 = 126;
+/// The number of columns in each glyph.
+pub const GLYPH_WIDTH: usize = 5;
+
+progmem! {
+    pub static FONT: [u8; 475] = [
+        0,0,0,0,0, // ' '
+        0,0,95,0,0, // '!'
+        0,7,0,7,0, // '"'
+        20,127,20,127,20, // '#'
+        36,42,127,42,18, // '$'
+        35,19,8,100,98, // '%'
+        54,73,85,34,80, // '&'
+        0,4,3,0,0, // '\''
+        0,28,34,65,0, // '('
+        0,65,34,28,0, // ')'
+        20,8,62,8,20, // '*'
+        8,8,62,8,8, // '+'
+        0,80,48,0,0, // ','
+        8,8,8,8,8, // '-'
+        0,96,96,0,0, // '.'
+        32,16,8,4,2, // '/'
+        62,81,73,69,62, // '0'
+        0,66,127,64,0, // '1'
+        66,97,81,73,70, // '2'
+        33,65,69,75,49, // '3'
+        24,20,18,127,16, // '4'
+        39,69,69,69,57, // '5'
+        60,74,73,73,48, // '6'
+        1,113,9,5,3, // '7'
+        54,73,73,73,54, // '8'
+        6,73,73,41,30, // '9'
+        0,54,54,0,0, // ':'
+        0,86,54,0,0, // ';'
+        8,20,34,65,0, // '<'
+        20,20,20,20,20, // '='
+        0,65,34,20,8, // '>'
+        2,1,81,9,6, // '?'
+        50,73,121,65,62, // '@'
+        126,9,9,9,126, // 'A'
+        127,73,73,73,54, // 'B'
+        62,65,65,65,34, // 'C'
+        127,65,65,34,28, // 'D'
+        127,73,73,73,65, // 'E'
+        127,9,9,9,1, // 'F'
+        62,65,73,73,122, // 'G'
+        127,8,8,8,127, // 'H'
+        0,65,127,65,0, // 'I'
+        32,64,65,63,1, // 'J'
+        127,8,20,34,65, // 'K'
+        127,64,64,64,64, // 'L'
+        127,2,12,2,127, // 'M'
+        127,4,8,16,127, // 'N'
+        62,65,65,65,62, // 'O'
+        127,9,9,9,6, // 'P'
+        62,65,81,33,94, // 'Q'
+        127,9,25,41,70, // 'R'
+        70,73,73,73,49, // 'S'
+        1,1,127,1,1, // 'T'
+        63,64,64,64,63, // 'U'
+        31,32,64,32,31, // 'V'
+        63,64,56,64,63, // 'W'
+        99,20,8,20,99, // 'X'
+        7,8,112,8,7, // 'Y'
+        97,81,73,69,67, // 'Z'
+        0,127,65,65,0, // '['
+        2,4,8,16,32, // '\\'
+        0,65,65,127,0, // ']'
+        4,2,1,2,4, // '^'
+        64,64,64,64,64, // '_'
+        0,1,2,4,0, // '`'
+        32,84,84,84,120, // 'a'
+        127,72,68,68,56, // 'b'
+        56,68,68,68,32, // 'c'
+        56,68,68,72,127, // 'd'
+        56,84,84,84,24, // 'e'
+        8,126,9,1,2, // 'f'
+        24,164,164,164,124, // 'g'
+        127,8,4,4,120, // 'h'
+        0,68,125,64,0, // 'i'
+        64,128,132,125,0, // 'j'
+        127,16,40,68,0, // 'k'
+        0,65,127,64,0, // 'l'
+        124,4,24,4,120, // 'm'
+        124,8,4,4,120, // 'n'
+        56,68,68,68,56, // 'o'
+        252,36,36,36,24, // 'p'
+        24,36,36,36,252, // 'q'
+        124,8,4,4,8, // 'r'
+        72,84,84,84,36, // 's'
+        4,63,68,64,32, // 't'
+        60,64,64,32,124, // 'u'
+        28,32,64,32,28, // 'v'
+        60,64,48,64,60, // 'w'
+        68,40,16,40,68, // 'x'
+        28,160,160,160,124, // 'y'
+        68,100,84,76,68, // 'z'
+        0,8,54,65,0, // '{'
+        0,0,127,0,0, // '|'
+        0,65,54,8,0, // '}'
+        8,4,8,16,8, // '~'
+    ];
+}
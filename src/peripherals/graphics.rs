@@ -0,0 +1,385 @@
+//! Drawing primitives for anything that holds pixels for part of the display.
+//!
+//! There isn't the RAM to hold the whole screen, so drawing is done either on a `Page` from
+//! `Display::render_pages`, or a `Region` covering a small part of the screen, such as a health
+//! bar, which can be sent on its own. Both use screen coordinates and clip anything outside
+//! themselves, so the same drawing code works on either.
+
+#![allow(dead_code)]
+
+use crate::{
+    hal::{format_decimal, progmem::ByteBundle, twi},
+    peripherals::{
+        display::{Display, ROWS, SSD1306_COLUMNADDR, SSD1306_COMMAND, SSD1306_PAGEADDR, WIDTH},
+        text::{self, CHAR_WIDTH},
+    },
+};
+
+/// The part of the screen a canvas covers. The right and bottom edges are exclusive.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Bounds {
+    pub left: i16,
+    pub top: i16,
+    pub right: i16,
+    pub bottom: i16,
+}
+
+pub trait Canvas {
+    fn bounds(&self) -> Bounds;
+
+    /// Sets or clears a pixel. Pixels outside the bounds are ignored.
+    fn set_pixel(&mut self, x: i16, y: i16, on: bool);
+
+    /// Draws a line between the two points, including both ends, using Bresenham's algorithm.
+    fn draw_line(&mut self, x0: i16, y0: i16, x1: i16, y1: i16, on: bool) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+
+        let (mut x, mut y) = (x0, y0);
+        let mut err = dx + dy;
+        loop {
+            self.set_pixel(x, y, on);
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let err2 = err * 2;
+            if err2 >= dy {
+                err += dy;
+                x += step_x;
+            }
+            if err2 <= dx {
+                err += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws the outline of a rectangle, with its top left corner at the given point.
+    fn draw_rect(&mut self, x: i16, y: i16, width: i16, height: i16, on: bool) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+
+        self.fill_rect(x, y, width, 1, on);
+        self.fill_rect(x, y + height - 1, width, 1, on);
+        self.fill_rect(x, y, 1, height, on);
+        self.fill_rect(x + width - 1, y, 1, height, on);
+    }
+
+    /// Fills a rectangle, with its top left corner at the given point.
+    fn fill_rect(&mut self, x: i16, y: i16, width: i16, height: i16, on: bool) {
+        // Clipped first, so a page doesn't loop over the rows that belong to the others.
+        let bounds = self.bounds();
+        let (left, right) = (x.max(bounds.left), (x + width).min(bounds.right));
+        let (top, bottom) = (y.max(bounds.top), (y + height).min(bounds.bottom));
+
+        for y in top..bottom {
+            for x in left..right {
+                self.set_pixel(x, y, on);
+            }
+        }
+    }
+
+    /// Draws the outline of a circle using the midpoint algorithm.
+    fn draw_circle(&mut self, centre_x: i16, centre_y: i16, radius: i16, on: bool) {
+        let (mut x, mut y) = (radius, 0);
+        let mut err = 1 - radius;
+
+        // Each point found in the first octant is mirrored into the other seven.
+        while x >= y {
+            for &(px, py) in &[(x, y), (y, x), (-y, x), (-x, y)] {
+                self.set_pixel(centre_x + px, centre_y + py, on);
+                self.set_pixel(centre_x - px, centre_y - py, on);
+            }
+
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Draws a bitmap with its top left corner at the given point. Only the pixels set in the
+    /// mask are drawn, so the bitmap can have transparent parts.
+    ///
+    /// Both are stored like the display: rows of 8 pixel high columns, with the top pixel in the
+    /// lowest bit. `width` is the number of columns in each row.
+    fn draw_bitmap<B, M>(&mut self, x: i16, y: i16, width: usize, bitmap: &B, mask: &M)
+    where
+        B: ByteBundle + ?Sized,
+        M: ByteBundle + ?Sized,
+        Self: Sized,
+    {
+        assert!(width > 0, "Zero width bitmap");
+        assert_eq!(bitmap.length(), mask.length(), "Mismatched lengths");
+
+        let bounds = self.bounds();
+        for (i, (column, mask)) in bitmap.bytes().zip(mask.bytes()).enumerate() {
+            let col_x = x + (i % width) as i16;
+            let col_y = y + (i / width) as i16 * 8;
+            if col_x < bounds.left
+                || col_x >= bounds.right
+                || col_y + 8 <= bounds.top
+                || col_y >= bounds.bottom
+            {
+                continue;
+            }
+
+            for bit in (0..8).filter(|bit| mask & (1 << bit) != 0) {
+                self.set_pixel(col_x, col_y + bit, column & (1 << bit) != 0);
+            }
+        }
+    }
+
+    /// Draws text with the top left of the first character at the given point, returning the X
+    /// coordinate just past its end.
+    ///
+    /// Only the pixels of the characters are drawn. For inverted text, fill a rectangle first
+    /// and draw the text over it with `on` cleared.
+    fn draw_text<T: AsRef<[u8]> + ?Sized>(&mut self, x: i16, y: i16, text: &T, on: bool) -> i16
+    where
+        Self: Sized,
+    {
+        let bounds = self.bounds();
+        let mut x = x;
+        for &c in text.as_ref() {
+            // Skip the characters that are off the canvas, rather than drawing them pixel by pixel.
+            let visible = x < bounds.right
+                && x + CHAR_WIDTH as i16 > bounds.left
+                && y < bounds.bottom
+                && y + 8 > bounds.top;
+
+            if visible {
+                for (i, column) in text::glyph(c).iter().enumerate() {
+                    for bit in (0..8).filter(|bit| column & (1 << bit) != 0) {
+                        self.set_pixel(x + i as i16, y + bit, on);
+                    }
+                }
+            }
+
+            x += CHAR_WIDTH as i16;
+        }
+
+        x
+    }
+
+    /// Draws a number in decimal, the same way as `draw_text`.
+    fn draw_number(&mut self, x: i16, y: i16, number: u32, on: bool) -> i16
+    where
+        Self: Sized,
+    {
+        let mut buf = [0; 10];
+        let digits = format_decimal(number, &mut buf);
+        self.draw_text(x, y, digits, on)
+    }
+}
+
+/// A buffer for a small part of the screen, `W` pixels wide and `PAGES` pages tall, which can be
+/// drawn on and sent without redrawing the rest of the screen.
+///
+/// It's positioned on the display's pages, so its top edge has to be a multiple of 8 pixels.
+pub struct Region<const W: usize, const PAGES: usize> {
+    buf: [[u8; W]; PAGES],
+    x: u8,
+    page: u8,
+}
+
+impl<const W: usize, const PAGES: usize> Region<W, PAGES> {
+    /// Creates a cleared region with its top left at pixel column `x`, and at the top of `page`.
+    /// Panics if it's empty, or doesn't fit on the screen.
+    pub fn new(x: u8, page: u8) -> Self {
+        assert!(W > 0 && PAGES > 0, "Empty region");
+        assert!(
+            x as usize + W <= WIDTH as usize,
+            "Region past the right of the screen"
+        );
+        assert!(
+            page as usize + PAGES <= ROWS as usize,
+            "Region past the bottom of the screen"
+        );
+
+        Self {
+            buf: [[0; W]; PAGES],
+            x,
+            page,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buf = [[0; W]; PAGES];
+    }
+
+    /// Returns whether the pixel is set. Pixels outside the region are always clear.
+    pub fn get_pixel(&self, x: i16, y: i16) -> bool {
+        match self.locate(x, y) {
+            Some((page, col, mask)) => self.buf[page][col] & mask != 0,
+            None => false,
+        }
+    }
+
+    /// Returns the page and column in the buffer, and the bit mask, for a pixel in the region.
+    fn locate(&self, x: i16, y: i16) -> Option<(usize, usize, u8)> {
+        let bounds = self.bounds();
+        if x < bounds.left || x >= bounds.right || y < bounds.top || y >= bounds.bottom {
+            return None;
+        }
+
+        let (x, y) = ((x - bounds.left) as usize, (y - bounds.top) as usize);
+        Some((y / 8, x, 1 << (y % 8)))
+    }
+}
+
+impl<const W: usize, const PAGES: usize> Canvas for Region<W, PAGES> {
+    fn bounds(&self) -> Bounds {
+        Bounds {
+            left: self.x as i16,
+            top: self.page as i16 * 8,
+            right: self.x as i16 + W as i16,
+            bottom: (self.page as i16 + PAGES as i16) * 8,
+        }
+    }
+
+    fn set_pixel(&mut self, x: i16, y: i16, on: bool) {
+        if let Some((page, col, mask)) = self.locate(x, y) {
+            if on {
+                self.buf[page][col] |= mask;
+            } else {
+                self.buf[page][col] &= !mask;
+            }
+        }
+    }
+}
+
+impl Display {
+    /// Sends a region to its place on the screen, leaving the rest of the screen alone.
+    pub fn draw_region<const W: usize, const PAGES: usize>(
+        &mut self,
+        twi: &mut twi::TWI,
+        region: &Region<W, PAGES>,
+    ) -> Result<(), twi::TWIError> {
+        self.forget_area(region.x, region.page, W as u8, PAGES as u8);
+
+        // Each page is sent on its own, as the screen's pages may not be in order in the
+        // display's memory if it's been scrolled.
+        for (i, page) in region.buf.iter().enumerate() {
+            let memory_page = self.memory_page(region.page + i as u8);
+            let commands = [
+                SSD1306_COMMAND,
+                SSD1306_PAGEADDR,
+                memory_page, // Page start address
+                memory_page, // Page end
+                SSD1306_COLUMNADDR,
+                region.x,               // Column start address
+                region.x + W as u8 - 1, // Column end address
+            ];
+            twi.write(commands.as_ref())?;
+            self.send_data(twi, page)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peripherals::display::HEIGHT;
+
+    /// Draws the region as text, with a `#` for each set pixel.
+    fn render<const W: usize, const PAGES: usize>(region: &Region<W, PAGES>) -> Vec<String> {
+        let bounds = region.bounds();
+        (bounds.top..bounds.bottom)
+            .map(|y| {
+                (bounds.left..bounds.right)
+                    .map(|x| if region.get_pixel(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn regions_may_reach_the_screen_edges() {
+        let region = Region::<8, 2>::new(WIDTH - 8, ROWS - 2);
+        assert_eq!(region.bounds().right, WIDTH as i16);
+        assert_eq!(region.bounds().bottom, HEIGHT as i16);
+    }
+
+    #[test]
+    #[should_panic(expected = "Empty region")]
+    fn empty_regions_panic() {
+        Region::<0, 1>::new(0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Region past the right of the screen")]
+    fn regions_past_the_right_edge_panic() {
+        Region::<8, 1>::new(WIDTH - 7, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Region past the bottom of the screen")]
+    fn regions_past_the_bottom_panic() {
+        Region::<8, 2>::new(0, ROWS - 1);
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        let mut region = Region::<6, 1>::new(0, 0);
+        region.draw_line(0, 0, 5, 2, true);
+        region.draw_line(5, 7, 5, 5, true);
+
+        assert_eq!(
+            render(&region),
+            ["##....", "..##..", "....##", "......", "......", ".....#", ".....#", ".....#"]
+        );
+    }
+
+    #[test]
+    fn shapes_are_clipped_to_the_region() {
+        // The region starts on the second page, at x 10.
+        let mut region = Region::<6, 1>::new(10, 1);
+        region.fill_rect(8, 6, 4, 4, true);
+        region.draw_rect(12, 10, 10, 5, true);
+        region.draw_circle(13, 12, 1, true);
+
+        assert_eq!(
+            render(&region),
+            ["##....", "##....", "..####", "..##..", "..#.#.", "..##..", "..####", "......"]
+        );
+    }
+
+    #[test]
+    fn bitmaps_only_draw_masked_pixels() {
+        let mut region = Region::<3, 2>::new(0, 0);
+        region.fill_rect(0, 0, 3, 16, true);
+
+        // Two rows of two columns, starting 4 pixels down.
+        let bitmap = [0b0000_0000, 0b0000_1111, 0b0000_0000, 0b0000_0000];
+        let mask = [0b0000_1111, 0b0000_1111, 0b0000_0011, 0b0000_0000];
+        region.draw_bitmap(1, 4, 2, &bitmap[..], &mask[..]);
+
+        let rendered = render(&region);
+        assert_eq!(rendered[3..9], ["###", "#.#", "#.#", "#.#", "#.#", "###"]);
+        assert_eq!(rendered[11..15], ["###", "#.#", "#.#", "###"]);
+    }
+
+    #[test]
+    fn inverted_text_is_cut_out_of_a_fill() {
+        // Only the middle of the second character lands in the region.
+        let mut region = Region::<3, 1>::new(7, 1);
+        region.fill_rect(7, 8, 3, 8, true);
+        let end = region.draw_text(0, 8, "-+", false);
+
+        assert_eq!(end, CHAR_WIDTH as i16 * 2);
+        assert_eq!(
+            render(&region),
+            ["###", "#.#", "#.#", "...", "#.#", "#.#", "###", "###"]
+        );
+    }
+}
@@ -1,2 +1,7 @@
 pub mod button;
 pub mod display;
+mod font;
+pub mod graphics;
+pub mod page_buffer;
+pub mod scroll;
+pub mod text;
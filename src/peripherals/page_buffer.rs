@@ -0,0 +1,196 @@
+//! Renders the display a page at a time, so things can be drawn at any pixel position.
+//!
+//! A framebuffer for the whole display would take 1KB, half of the RAM. Instead the drawing code
+//! is run once for each of the display's 8 pages, which are 128x8 pixels, drawing into a 128 byte
+//! buffer that's sent to the display before moving on to the next page. Anything outside the
+//! current page is clipped, so the drawing code doesn't need to care which page it's on, but it
+//! does need to draw the same thing every time it's called.
+
+#![allow(dead_code)]
+
+use crate::{
+    hal::{
+        progmem::{ByteBundle, PGMSlice},
+        twi,
+    },
+    peripherals::{
+        display::{Display, ROWS, WIDTH},
+        graphics::{Bounds, Canvas},
+    },
+};
+
+/// The height of a page, in pixels.
+pub const PAGE_HEIGHT: i16 = 8;
+
+/// A 128x8 pixel strip of the display.
+///
+/// Each byte is a column of 8 pixels, with the top pixel in the lowest bit, which is how the
+/// display and the tiles store them.
+pub struct Page {
+    buf: [u8; WIDTH as usize],
+    index: u8,
+}
+
+impl Page {
+    const fn new() -> Page {
+        Page {
+            buf: [0; WIDTH as usize],
+            index: 0,
+        }
+    }
+
+    /// Which page this is, from 0 at the top.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// The Y coordinate of the page's top row of pixels.
+    pub fn top(&self) -> i16 {
+        self.index as i16 * PAGE_HEIGHT
+    }
+
+    pub fn clear(&mut self) {
+        self.buf = [0; WIDTH as usize];
+    }
+
+    /// Returns whether the pixel is set. Pixels outside the page are always clear.
+    pub fn get_pixel(&self, x: i16, y: i16) -> bool {
+        match self.locate(x, y) {
+            Some((col, mask)) => self.buf[col] & mask != 0,
+            None => false,
+        }
+    }
+
+    /// Draws columns of 8 pixels, in the same format as the page, with the top of the first
+    /// column at the given position. Set pixels are drawn, and clear ones are left alone.
+    pub fn draw_columns<T: ByteBundle + ?Sized>(&mut self, x: i16, y: i16, columns: &T) {
+        let shift = y - self.top();
+        if shift <= -PAGE_HEIGHT || shift >= PAGE_HEIGHT {
+            return;
+        }
+
+        for (i, column) in columns.bytes().enumerate() {
+            let col_x = x + i as i16;
+            if col_x < 0 {
+                continue;
+            }
+            if col_x >= WIDTH as i16 {
+                break;
+            }
+
+            self.buf[col_x as usize] |= if shift >= 0 {
+                column << shift
+            } else {
+                column >> -shift
+            };
+        }
+    }
+
+    /// Draws one of the 8x8 tiles generated by `build.rs`, skipping the data flag at the start.
+    pub fn draw_tile(&mut self, x: i16, y: i16, tile: &PGMSlice) {
+        self.draw_columns(x, y, &tile.slice(1..));
+    }
+
+    /// Returns the index into the buffer, and the bit mask, for a pixel on this page.
+    fn locate(&self, x: i16, y: i16) -> Option<(usize, u8)> {
+        let row = y - self.top();
+        if !(0..WIDTH as i16).contains(&x) || !(0..PAGE_HEIGHT).contains(&row) {
+            None
+        } else {
+            Some((x as usize, 1 << row))
+        }
+    }
+}
+
+impl Canvas for Page {
+    fn bounds(&self) -> Bounds {
+        Bounds {
+            left: 0,
+            top: self.top(),
+            right: WIDTH as i16,
+            bottom: self.top() + PAGE_HEIGHT,
+        }
+    }
+
+    fn set_pixel(&mut self, x: i16, y: i16, on: bool) {
+        if let Some((col, mask)) = self.locate(x, y) {
+            if on {
+                self.buf[col] |= mask;
+            } else {
+                self.buf[col] &= !mask;
+            }
+        }
+    }
+}
+
+impl Display {
+    /// Redraws the whole screen through a single page buffer.
+    ///
+    /// `draw` is called for each page, from top to bottom, with the page cleared. It should draw
+    /// the whole screen every time, as only what lands on the current page is kept.
+    pub fn render_pages<F: FnMut(&mut Page)>(
+        &mut self,
+        twi: &mut twi::TWI,
+        mut draw: F,
+    ) -> Result<(), twi::TWIError> {
+        self.select_whole_screen(twi)?;
+
+        let mut page = Page::new();
+        for index in 0..ROWS {
+            page.index = index;
+            page.clear();
+            draw(&mut page);
+
+            self.send_data(twi, &page.buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(index: u8) -> Page {
+        let mut page = Page::new();
+        page.index = index;
+        page
+    }
+
+    #[test]
+    fn columns_are_split_across_pages() {
+        // Drawn 3 pixels down from the top of page 1, so the bottom 3 rows land on page 2.
+        let columns = [0b1111_1111, 0b1000_0001];
+
+        let mut top = page(1);
+        top.draw_columns(126, 11, &columns[..]);
+        assert_eq!(top.buf[126..], [0b1111_1000, 0b0000_1000]);
+
+        let mut bottom = page(2);
+        bottom.draw_columns(126, 11, &columns[..]);
+        assert_eq!(bottom.buf[126..], [0b0000_0111, 0b0000_0100]);
+
+        // Off the left edge, and too far down.
+        let mut clipped = page(0);
+        clipped.draw_columns(-1, 0, &columns[..]);
+        clipped.draw_columns(0, 8, &columns[..]);
+        assert_eq!(clipped.buf[..2], [0b1000_0001, 0]);
+    }
+
+    #[test]
+    fn pixels_outside_the_page_are_ignored() {
+        let mut page = page(1);
+        page.set_pixel(3, 9, true);
+        page.set_pixel(3, 7, true);
+        page.set_pixel(-1, 9, true);
+        page.set_pixel(WIDTH as i16, 9, true);
+
+        assert!(page.get_pixel(3, 9));
+        assert!(!page.get_pixel(3, 7));
+        assert_eq!(page.buf.iter().filter(|&&b| b != 0).count(), 1);
+
+        page.set_pixel(3, 9, false);
+        assert_eq!(page.buf[3], 0);
+    }
+}
@@ -0,0 +1,186 @@
+//! The SSD1306's continuous scrolling, where the display moves the picture by itself.
+//!
+//! The picture keeps moving a column at a time until it's stopped. It's only moved on the way
+//! to the screen, not in the display's memory, so once it's stopped the screen has to be
+//! redrawn. That means it can't be used to move the game's view sideways, but it's fine for
+//! effects like a scrolling title.
+//!
+//! The pages given are pages of the display's memory, which are the same as the screen's rows
+//! unless `Display::scroll_rows` has been used.
+
+#![allow(dead_code)]
+
+use crate::{
+    hal::twi,
+    peripherals::display::{Display, SSD1306_COMMAND},
+};
+
+const SSD1306_RIGHT_HORIZONTAL_SCROLL: u8 = 0x26;
+const SSD1306_LEFT_HORIZONTAL_SCROLL: u8 = 0x27;
+const SSD1306_VERTICAL_AND_RIGHT_HORIZONTAL_SCROLL: u8 = 0x29;
+const SSD1306_VERTICAL_AND_LEFT_HORIZONTAL_SCROLL: u8 = 0x2A;
+const SSD1306_DEACTIVATE_SCROLL: u8 = 0x2E;
+const SSD1306_ACTIVATE_SCROLL: u8 = 0x2F;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ScrollDirection {
+    Left,
+    Right,
+}
+
+/// How many frames the display waits between each step of the scroll.
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ScrollInterval {
+    Frames2 = 0b111,
+    Frames3 = 0b100,
+    Frames4 = 0b101,
+    Frames5 = 0b000,
+    Frames25 = 0b110,
+    Frames64 = 0b001,
+    Frames128 = 0b010,
+    Frames256 = 0b011,
+}
+
+impl Display {
+    /// Starts scrolling the pages from `start_page` to `end_page` sideways.
+    pub fn start_horizontal_scroll(
+        &mut self,
+        twi: &mut twi::TWI,
+        direction: ScrollDirection,
+        start_page: u8,
+        end_page: u8,
+        interval: ScrollInterval,
+    ) -> Result<(), twi::TWIError> {
+        let command = match direction {
+            ScrollDirection::Left => SSD1306_LEFT_HORIZONTAL_SCROLL,
+            ScrollDirection::Right => SSD1306_RIGHT_HORIZONTAL_SCROLL,
+        };
+
+        let commands = [
+            SSD1306_COMMAND,
+            // The display ignores new scroll settings while it's scrolling.
+            SSD1306_DEACTIVATE_SCROLL,
+            command,
+            0x00, // Dummy byte
+            start_page,
+            interval as u8,
+            end_page,
+            0x00, // Dummy bytes
+            0xFF,
+            SSD1306_ACTIVATE_SCROLL,
+        ];
+        twi.write(commands.as_ref())
+    }
+
+    /// Starts scrolling the pages from `start_page` to `end_page` sideways, while the whole
+    /// screen also scrolls up by `vertical_offset` rows of pixels each step.
+    pub fn start_diagonal_scroll(
+        &mut self,
+        twi: &mut twi::TWI,
+        direction: ScrollDirection,
+        start_page: u8,
+        end_page: u8,
+        interval: ScrollInterval,
+        vertical_offset: u8,
+    ) -> Result<(), twi::TWIError> {
+        let command = match direction {
+            ScrollDirection::Left => SSD1306_VERTICAL_AND_LEFT_HORIZONTAL_SCROLL,
+            ScrollDirection::Right => SSD1306_VERTICAL_AND_RIGHT_HORIZONTAL_SCROLL,
+        };
+
+        let commands = [
+            SSD1306_COMMAND,
+            SSD1306_DEACTIVATE_SCROLL,
+            command,
+            0x00, // Dummy byte
+            start_page,
+            interval as u8,
+            end_page,
+            vertical_offset,
+            SSD1306_ACTIVATE_SCROLL,
+        ];
+        twi.write(commands.as_ref())
+    }
+
+    /// Stops a continuous scroll. The screen needs redrawing afterwards, so the whole of it is
+    /// forgotten.
+    pub fn stop_scroll(&mut self, twi: &mut twi::TWI) -> Result<(), twi::TWIError> {
+        self.invalidate();
+        twi.write([SSD1306_COMMAND, SSD1306_DEACTIVATE_SCROLL].as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hal::{
+            sim,
+            twi::tests::{init as init_twi, simulate_bus},
+        },
+        peripherals::display::tests::take_writes,
+    };
+
+    #[test]
+    fn scroll_commands_are_sent_in_full() {
+        let _hw = sim::reset();
+        let bus = simulate_bus(false);
+        let mut twi = init_twi();
+        let mut display = Display::init(&mut twi).ok().unwrap();
+        take_writes(&bus);
+
+        let result = display.start_horizontal_scroll(
+            &mut twi,
+            ScrollDirection::Right,
+            0,
+            7,
+            ScrollInterval::Frames2,
+        );
+        assert!(result.is_ok());
+        let result = display.start_horizontal_scroll(
+            &mut twi,
+            ScrollDirection::Left,
+            2,
+            3,
+            ScrollInterval::Frames256,
+        );
+        assert!(result.is_ok());
+        assert_eq!(
+            take_writes(&bus),
+            [
+                [0x00, 0x2E, 0x26, 0x00, 0, 0b111, 7, 0x00, 0xFF, 0x2F],
+                [0x00, 0x2E, 0x27, 0x00, 2, 0b011, 3, 0x00, 0xFF, 0x2F]
+            ]
+        );
+
+        let result = display.start_diagonal_scroll(
+            &mut twi,
+            ScrollDirection::Right,
+            1,
+            4,
+            ScrollInterval::Frames5,
+            1,
+        );
+        assert!(result.is_ok());
+        let result = display.start_diagonal_scroll(
+            &mut twi,
+            ScrollDirection::Left,
+            0,
+            7,
+            ScrollInterval::Frames25,
+            63,
+        );
+        assert!(result.is_ok());
+        assert_eq!(
+            take_writes(&bus),
+            [
+                [0x00, 0x2E, 0x29, 0x00, 1, 0b000, 4, 1, 0x2F],
+                [0x00, 0x2E, 0x2A, 0x00, 0, 0b110, 7, 63, 0x2F]
+            ]
+        );
+
+        assert!(display.stop_scroll(&mut twi).is_ok());
+        assert_eq!(take_writes(&bus), [[0x00, 0x2E]]);
+    }
+}
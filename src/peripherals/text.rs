@@ -0,0 +1,120 @@
+//! Draws text with the font generated from `assets/font.png` by `build.rs`.
+//!
+//! Each character is 5 pixels wide, plus a column of space after it, so 21 fit across the
+//! screen. They're 8 pixels tall, so a line of text fits on one of the display's pages, and can
+//! be sent straight to the display without a buffer. Anything that isn't printable ASCII is
+//! drawn as a `?`.
+
+use crate::{
+    hal::{format_decimal, progmem::PGMSlice, twi},
+    peripherals::{
+        display::{Display, SSD1306_COLUMNADDR, SSD1306_COMMAND, SSD1306_PAGEADDR, WIDTH},
+        font::{FIRST_CHAR, FONT, GLYPH_WIDTH, LAST_CHAR},
+    },
+};
+use core::iter;
+
+/// The width of a character, including the space after it.
+pub const CHAR_WIDTH: u8 = GLYPH_WIDTH as u8 + 1;
+
+/// Returns the columns of a character's glyph.
+pub fn glyph(c: u8) -> PGMSlice {
+    let c = if (FIRST_CHAR..=LAST_CHAR).contains(&c) {
+        c
+    } else {
+        b'?'
+    };
+
+    let start = (c - FIRST_CHAR) as usize * GLYPH_WIDTH;
+    FONT.as_slice().slice(start..start + GLYPH_WIDTH)
+}
+
+impl Display {
+    /// Draws a line of text with its top left at pixel column `x` on `page`. Anything past the
+    /// right edge of the screen is cut off. Inverted text is drawn dark on a lit background.
+    ///
+    /// Returns the column just past the end of the text, so more can be drawn after it.
+    pub fn draw_text<T: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        twi: &mut twi::TWI,
+        x: u8,
+        page: u8,
+        text: &T,
+        inverted: bool,
+    ) -> Result<u8, twi::TWIError> {
+        let text = text.as_ref();
+        let width = (text.len() * CHAR_WIDTH as usize).min(WIDTH.saturating_sub(x) as usize) as u8;
+        if width == 0 {
+            return Ok(x);
+        }
+
+        let memory_page = self.memory_page(page);
+        let commands = [
+            SSD1306_COMMAND,
+            SSD1306_PAGEADDR,
+            memory_page, // Page start address
+            memory_page, // Page end
+            SSD1306_COLUMNADDR,
+            x,             // Column start address
+            x + width - 1, // Column end address
+        ];
+        self.forget_area(x, page, width, 1);
+        twi.write(commands.as_ref())?;
+
+        let columns = text
+            .iter()
+            .flat_map(|&c| glyph(c).iter().chain(iter::once(0)))
+            .take(width as usize);
+
+        // Gathered up so each write is as full as it can be.
+        let mut buf = [0; twi::BUFFER_LEN - 1];
+        let mut len = 0;
+        for column in columns {
+            buf[len] = if inverted { !column } else { column };
+            len += 1;
+
+            if len == buf.len() {
+                self.send_data(twi, &buf)?;
+                len = 0;
+            }
+        }
+        self.send_data(twi, &buf[..len])?;
+
+        Ok(x + width)
+    }
+
+    /// Draws a number in decimal, the same way as `draw_text`.
+    pub fn draw_number(
+        &mut self,
+        twi: &mut twi::TWI,
+        x: u8,
+        page: u8,
+        number: u32,
+        inverted: bool,
+    ) -> Result<u8, twi::TWIError> {
+        let mut buf = [0; 10];
+        let digits = format_decimal(number, &mut buf);
+        self.draw_text(twi, x, page, digits, inverted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::progmem::ByteBundle;
+
+    #[test]
+    fn unprintable_characters_use_the_question_mark() {
+        let mut question = [0; GLYPH_WIDTH];
+        glyph(b'?').copy_to(&mut question);
+        assert_ne!(question, [0; GLYPH_WIDTH]);
+
+        for &c in &[0, b'\n', 0x7F, 0xFF] {
+            assert!(glyph(c).iter().eq(question.iter().copied()));
+        }
+
+        // The first and last glyphs are in range.
+        assert!(glyph(b' ').iter().all(|column| column == 0));
+        assert!(!glyph(b'~').iter().eq(question.iter().copied()));
+    }
+}
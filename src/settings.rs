@@ -0,0 +1,320 @@
+//! The display settings, kept in the EEPROM just after the crash log.
+//!
+//! They're stored as a magic byte, so a blank EEPROM can be told apart from saved settings, then
+//! the contrast, a byte of flags, and the multiplex ratio. Pressing up on the title screen opens a
+//! menu for changing them, which also has the display's pixel test and sleep.
+
+use crate::{
+    crash_log,
+    game::Input,
+    hal::{clock::Clock, eeprom::EEPROM, format_decimal, twi},
+    peripherals::{
+        display::{Display, HEIGHT, WIDTH},
+        text::CHAR_WIDTH,
+    },
+};
+
+const SETTINGS_ADDR: u16 = crash_log::LOG_END;
+const MAGIC: u8 = 0x5E;
+const SETTINGS_SIZE: u16 = 4;
+
+const FLAG_INVERTED: u8 = 1 << 0;
+const FLAG_FLIP_HORIZONTAL: u8 = 1 << 1;
+const FLAG_FLIP_VERTICAL: u8 = 1 << 2;
+
+/// How much left and right change the contrast by.
+const CONTRAST_STEP: u8 = 16;
+/// How much left and right change the multiplex ratio by, so whole pages are shown or hidden.
+const MULTIPLEX_STEP: u8 = 8;
+/// The fewest rows the display can drive.
+const MIN_MULTIPLEX: u8 = 16;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Settings {
+    pub contrast: u8,
+    pub inverted: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// How many rows of pixels are driven, from the top.
+    pub multiplex: u8,
+}
+
+impl Settings {
+    /// What the display does after a reset.
+    pub const DEFAULT: Settings = Settings {
+        contrast: 0x7F,
+        inverted: false,
+        flip_horizontal: false,
+        flip_vertical: false,
+        multiplex: HEIGHT,
+    };
+
+    /// Loads the saved settings, or the defaults if there aren't any.
+    pub fn load(eeprom: &EEPROM) -> Settings {
+        let mut bytes = [0; SETTINGS_SIZE as usize];
+        eeprom.read_bytes(SETTINGS_ADDR, &mut bytes);
+        Settings::from_bytes(bytes).unwrap_or(Settings::DEFAULT)
+    }
+
+    pub fn save(self, eeprom: &mut EEPROM) {
+        eeprom.write_bytes(SETTINGS_ADDR, &self.to_bytes());
+    }
+
+    fn to_bytes(self) -> [u8; SETTINGS_SIZE as usize] {
+        let mut flags = 0;
+        if self.inverted {
+            flags |= FLAG_INVERTED;
+        }
+        if self.flip_horizontal {
+            flags |= FLAG_FLIP_HORIZONTAL;
+        }
+        if self.flip_vertical {
+            flags |= FLAG_FLIP_VERTICAL;
+        }
+
+        [MAGIC, self.contrast, flags, self.multiplex]
+    }
+
+    fn from_bytes(bytes: [u8; SETTINGS_SIZE as usize]) -> Option<Settings> {
+        // An out of range multiplex ratio would panic when applied.
+        if bytes[0] != MAGIC || !(MIN_MULTIPLEX..=HEIGHT).contains(&bytes[3]) {
+            return None;
+        }
+
+        Some(Settings {
+            contrast: bytes[1],
+            inverted: bytes[2] & FLAG_INVERTED != 0,
+            flip_horizontal: bytes[2] & FLAG_FLIP_HORIZONTAL != 0,
+            flip_vertical: bytes[2] & FLAG_FLIP_VERTICAL != 0,
+            multiplex: bytes[3],
+        })
+    }
+
+    /// Sends the settings to the display. The screen needs redrawing afterwards, in case it's
+    /// been flipped horizontally.
+    pub fn apply(self, display: &mut Display, twi: &mut twi::TWI) -> Result<(), twi::TWIError> {
+        display.set_contrast(twi, self.contrast)?;
+        display.set_inverted(twi, self.inverted)?;
+        display.set_flip_horizontal(twi, self.flip_horizontal)?;
+        display.set_flip_vertical(twi, self.flip_vertical)?;
+        display.set_multiplex_ratio(twi, self.multiplex)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Item {
+    Contrast,
+    Multiplex,
+    Invert,
+    FlipHorizontal,
+    FlipVertical,
+    PixelTest,
+    Sleep,
+    SaveAndExit,
+}
+
+/// One per page, which fills the screen. The multiplex ratio is near the top, so it can still be
+/// seen and put back when only the top two pages are driven.
+const ITEMS: [Item; 8] = [
+    Item::Contrast,
+    Item::Multiplex,
+    Item::Invert,
+    Item::FlipHorizontal,
+    Item::FlipVertical,
+    Item::PixelTest,
+    Item::Sleep,
+    Item::SaveAndExit,
+];
+
+/// How many characters fit on a line.
+const LINE_LEN: usize = (WIDTH / CHAR_WIDTH) as usize;
+
+impl Item {
+    fn label(self) -> &'static str {
+        match self {
+            Item::Contrast => "Contrast",
+            Item::Multiplex => "Rows driven",
+            Item::Invert => "Invert",
+            Item::FlipHorizontal => "Flip horizontal",
+            Item::FlipVertical => "Flip vertical",
+            Item::PixelTest => "Pixel test",
+            Item::Sleep => "Sleep",
+            Item::SaveAndExit => "Save and exit",
+        }
+    }
+}
+
+/// Shows the settings menu, returning once it's been left.
+///
+/// Up and down choose an item, and left and right change it. Changes are shown straight away,
+/// and saved when leaving the menu.
+pub fn menu(
+    eeprom: &mut EEPROM,
+    display: &mut Display,
+    twi: &mut twi::TWI,
+    input: &mut Input,
+    clock: &Clock,
+) -> Result<(), twi::TWIError> {
+    let mut settings = Settings::load(eeprom);
+    let mut selected = 0;
+
+    display.clear_display(twi)?;
+
+    loop {
+        for (i, &item) in ITEMS.iter().enumerate() {
+            let line = menu_line(item, &settings);
+            display.draw_text(twi, 0, i as u8, &line, i == selected)?;
+        }
+
+        wait_for_press(input, clock);
+
+        if input.up() {
+            selected = selected.checked_sub(1).unwrap_or(ITEMS.len() - 1);
+            continue;
+        }
+        if input.down() {
+            selected = (selected + 1) % ITEMS.len();
+            continue;
+        }
+
+        let right = input.right();
+        match ITEMS[selected] {
+            Item::Contrast if right => {
+                settings.contrast = settings.contrast.saturating_add(CONTRAST_STEP);
+                display.set_contrast(twi, settings.contrast)?;
+            }
+            Item::Contrast => {
+                settings.contrast = settings.contrast.saturating_sub(CONTRAST_STEP);
+                display.set_contrast(twi, settings.contrast)?;
+            }
+            Item::Invert => {
+                settings.inverted = !settings.inverted;
+                display.set_inverted(twi, settings.inverted)?;
+            }
+            Item::FlipHorizontal => {
+                settings.flip_horizontal = !settings.flip_horizontal;
+                display.set_flip_horizontal(twi, settings.flip_horizontal)?;
+
+                // The menu's still drawn the old way round.
+                display.clear_display(twi)?;
+            }
+            Item::FlipVertical => {
+                settings.flip_vertical = !settings.flip_vertical;
+                display.set_flip_vertical(twi, settings.flip_vertical)?;
+            }
+            Item::Multiplex if right => {
+                settings.multiplex = (settings.multiplex + MULTIPLEX_STEP).min(HEIGHT);
+                display.set_multiplex_ratio(twi, settings.multiplex)?;
+            }
+            Item::Multiplex => {
+                settings.multiplex = (settings.multiplex - MULTIPLEX_STEP).max(MIN_MULTIPLEX);
+                display.set_multiplex_ratio(twi, settings.multiplex)?;
+            }
+            Item::PixelTest => {
+                display.set_entire_display_on(twi, true)?;
+                wait_for_press(input, clock);
+                display.set_entire_display_on(twi, false)?;
+            }
+            Item::Sleep => {
+                display.set_display_on(twi, false)?;
+                wait_for_press(input, clock);
+                display.set_display_on(twi, true)?;
+            }
+            Item::SaveAndExit => {
+                settings.save(eeprom);
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Lays out an item's line, with its label on the left and its value on the right.
+fn menu_line(item: Item, settings: &Settings) -> [u8; LINE_LEN] {
+    let mut line = [b' '; LINE_LEN];
+    let label = item.label().as_bytes();
+    line[..label.len()].copy_from_slice(label);
+
+    let on_off = |on| if on { &b"On"[..] } else { &b"Off"[..] };
+    let mut buf = [0; 10];
+    let value = match item {
+        Item::Contrast => format_decimal(settings.contrast as u32, &mut buf),
+        Item::Invert => on_off(settings.inverted),
+        Item::FlipHorizontal => on_off(settings.flip_horizontal),
+        Item::FlipVertical => on_off(settings.flip_vertical),
+        Item::Multiplex => format_decimal(settings.multiplex as u32, &mut buf),
+        Item::PixelTest | Item::Sleep | Item::SaveAndExit => &[],
+    };
+    line[LINE_LEN - value.len()..].copy_from_slice(value);
+
+    line
+}
+
+fn wait_for_press(input: &mut Input, clock: &Clock) {
+    loop {
+        let now = clock.now();
+        if input.update(now) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip_through_bytes() {
+        let settings = Settings {
+            contrast: 0x30,
+            inverted: true,
+            flip_horizontal: false,
+            flip_vertical: true,
+            multiplex: 32,
+        };
+        let bytes = settings.to_bytes();
+        assert_eq!(bytes, [MAGIC, 0x30, 0b101, 32]);
+        assert_eq!(Settings::from_bytes(bytes), Some(settings));
+
+        let flipped = Settings::from_bytes([MAGIC, 0, 0b010, HEIGHT]).unwrap();
+        assert!(!flipped.inverted && flipped.flip_horizontal && !flipped.flip_vertical);
+    }
+
+    #[test]
+    fn blank_or_bad_settings_are_rejected() {
+        // A blank EEPROM.
+        assert_eq!(Settings::from_bytes([0xFF; SETTINGS_SIZE as usize]), None);
+        assert_eq!(Settings::from_bytes([0, 0x7F, 0, HEIGHT]), None);
+
+        // Multiplex ratios the display can't take.
+        assert_eq!(Settings::from_bytes([MAGIC, 0x7F, 0, 8]), None);
+        assert_eq!(Settings::from_bytes([MAGIC, 0x7F, 0, 0xFF]), None);
+    }
+
+    #[test]
+    fn menu_values_are_right_aligned() {
+        let mut settings = Settings::DEFAULT;
+        settings.contrast = 5;
+        settings.inverted = true;
+
+        assert_eq!(
+            &menu_line(Item::Contrast, &settings),
+            b"Contrast            5"
+        );
+        assert_eq!(
+            &menu_line(Item::Multiplex, &settings),
+            b"Rows driven        64"
+        );
+        assert_eq!(
+            &menu_line(Item::Invert, &settings),
+            b"Invert             On"
+        );
+        assert_eq!(
+            &menu_line(Item::FlipVertical, &settings),
+            b"Flip vertical     Off"
+        );
+        assert_eq!(
+            &menu_line(Item::SaveAndExit, &settings),
+            b"Save and exit        "
+        );
+    }
+}